@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS banned_names;
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS disabled;
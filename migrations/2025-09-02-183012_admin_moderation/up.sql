@@ -0,0 +1,8 @@
+ALTER TABLE users
+    ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE banned_names
+(
+    name       VARCHAR(255) NOT NULL PRIMARY KEY,
+    created_at TIMESTAMP    NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_invoice_receive_id;
+
+ALTER TABLE invoice
+    DROP COLUMN IF EXISTS receive_id;
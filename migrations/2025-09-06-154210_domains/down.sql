@@ -0,0 +1,20 @@
+DELETE FROM zaps
+WHERE id IN (SELECT invoice.id
+             FROM invoice
+                      JOIN users ON users.id = invoice.user_id
+             WHERE users.domain_id IS NOT NULL);
+DELETE FROM invoice
+WHERE user_id IN (SELECT id FROM users WHERE domain_id IS NOT NULL);
+DELETE FROM users
+WHERE domain_id IS NOT NULL;
+
+DROP INDEX IF EXISTS idx_user_name;
+DROP INDEX IF EXISTS idx_user_pk;
+
+CREATE UNIQUE INDEX idx_user_pk ON users (pubkey);
+CREATE UNIQUE INDEX idx_user_name ON users (name);
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS domain_id;
+
+DROP TABLE IF EXISTS domains;
@@ -0,0 +1,20 @@
+CREATE TABLE domains
+(
+    id              SERIAL PRIMARY KEY,
+    domain          VARCHAR(255) NOT NULL UNIQUE,
+    min_sendable    BIGINT       NOT NULL,
+    max_sendable    BIGINT       NOT NULL,
+    comment_allowed INTEGER      NOT NULL DEFAULT 100
+);
+
+-- users without a domain belong to the server's primary domain
+ALTER TABLE users
+    ADD COLUMN domain_id INTEGER REFERENCES domains (id);
+
+ALTER TABLE users
+    DROP CONSTRAINT IF EXISTS users_name_key;
+DROP INDEX IF EXISTS idx_user_name;
+DROP INDEX IF EXISTS idx_user_pk;
+
+CREATE UNIQUE INDEX idx_user_name ON users (COALESCE(domain_id, 0), name);
+CREATE UNIQUE INDEX idx_user_pk ON users (COALESCE(domain_id, 0), pubkey);
@@ -0,0 +1,17 @@
+CREATE TABLE pay_links
+(
+    id           SERIAL PRIMARY KEY,
+    user_id      INTEGER      NOT NULL REFERENCES users (id),
+    slug         VARCHAR(64)  NOT NULL,
+    description  VARCHAR(255) NOT NULL,
+    amount_msats BIGINT       NOT NULL,
+    disabled     BOOLEAN      NOT NULL DEFAULT FALSE,
+    created_at   TIMESTAMP    NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_pay_links_user_slug ON pay_links (user_id, slug);
+
+ALTER TABLE invoice
+    ADD COLUMN pay_link_id INTEGER REFERENCES pay_links (id);
+
+CREATE INDEX idx_invoice_pay_link_id ON invoice (pay_link_id);
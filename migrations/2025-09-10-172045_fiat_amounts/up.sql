@@ -0,0 +1,11 @@
+ALTER TABLE pay_links
+    ADD COLUMN fiat_currency VARCHAR(3);
+ALTER TABLE pay_links
+    ADD COLUMN fiat_amount BIGINT;
+
+ALTER TABLE invoice
+    ADD COLUMN fiat_currency VARCHAR(3);
+ALTER TABLE invoice
+    ADD COLUMN fiat_amount BIGINT;
+ALTER TABLE invoice
+    ADD COLUMN fiat_rate DOUBLE PRECISION;
@@ -0,0 +1,5 @@
+-- hash of the callback parameters, repeated callbacks reuse the pending invoice with the same key
+ALTER TABLE invoice
+    ADD COLUMN dedupe_key VARCHAR(64);
+
+CREATE INDEX idx_invoice_dedupe_key ON invoice (user_id, dedupe_key);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_invoice_payment_hash;
+
+ALTER TABLE invoice
+    DROP COLUMN IF EXISTS payment_hash;
@@ -0,0 +1,4 @@
+ALTER TABLE invoice
+    ADD COLUMN payment_hash VARCHAR(64);
+
+CREATE INDEX idx_invoice_payment_hash ON invoice (payment_hash);
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_invoice_pending_dedupe_key;
+
+CREATE INDEX idx_invoice_dedupe_key ON invoice (user_id, dedupe_key);
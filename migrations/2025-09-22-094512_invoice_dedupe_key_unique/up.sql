@@ -0,0 +1,8 @@
+-- keys are only given to callbacks with an idempotency key now, older invoices aren't reused
+UPDATE invoice
+SET dedupe_key = NULL;
+
+DROP INDEX IF EXISTS idx_invoice_dedupe_key;
+
+-- a single pending invoice per key, a concurrent callback with the same key fails to insert
+CREATE UNIQUE INDEX idx_invoice_pending_dedupe_key ON invoice (user_id, dedupe_key) WHERE state = 0;
@@ -0,0 +1,2 @@
+-- names are looked up case-insensitively
+CREATE INDEX idx_user_lower_name ON users (LOWER(name));
@@ -0,0 +1,6 @@
+ALTER TABLE invoice
+    ADD CONSTRAINT invoice_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) NOT VALID;
+ALTER TABLE pay_links
+    ADD CONSTRAINT pay_links_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) NOT VALID;
+ALTER TABLE deposits
+    ADD CONSTRAINT deposits_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) NOT VALID;
@@ -0,0 +1,4 @@
+-- released users are deleted, their invoices, pay links and deposits are kept as history
+ALTER TABLE invoice DROP CONSTRAINT IF EXISTS invoice_user_id_fkey;
+ALTER TABLE pay_links DROP CONSTRAINT IF EXISTS pay_links_user_id_fkey;
+ALTER TABLE deposits DROP CONSTRAINT IF EXISTS deposits_user_id_fkey;
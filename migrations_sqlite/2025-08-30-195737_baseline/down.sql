@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS zaps;
+
+DROP TABLE IF EXISTS invoice;
+
+DROP TABLE IF EXISTS users;
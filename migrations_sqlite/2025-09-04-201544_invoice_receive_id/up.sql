@@ -0,0 +1,4 @@
+ALTER TABLE invoice
+    ADD COLUMN receive_id VARCHAR(255);
+
+CREATE INDEX idx_invoice_receive_id ON invoice (receive_id);
@@ -0,0 +1,37 @@
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+DELETE FROM zaps
+WHERE id IN (SELECT invoice.id
+             FROM invoice
+                      JOIN users ON users.id = invoice.user_id
+             WHERE users.domain_id IS NOT NULL);
+DELETE FROM invoice
+WHERE user_id IN (SELECT id FROM users WHERE domain_id IS NOT NULL);
+
+CREATE TABLE users_old
+(
+    id            INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    pubkey        VARCHAR(66)  NOT NULL,
+    name          VARCHAR(255) NOT NULL UNIQUE,
+    disabled_zaps BOOLEAN      NOT NULL DEFAULT FALSE,
+    disabled      BOOLEAN      NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO users_old (id, pubkey, name, disabled_zaps, disabled)
+SELECT id, pubkey, name, disabled_zaps, disabled
+FROM users
+WHERE domain_id IS NULL;
+
+DROP TABLE users;
+ALTER TABLE users_old RENAME TO users;
+
+CREATE UNIQUE INDEX idx_user_pk ON users (pubkey);
+CREATE UNIQUE INDEX idx_user_name ON users (name);
+
+DROP TABLE domains;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,40 @@
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE domains
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    domain          VARCHAR(255) NOT NULL UNIQUE,
+    min_sendable    BIGINT       NOT NULL,
+    max_sendable    BIGINT       NOT NULL,
+    comment_allowed INTEGER      NOT NULL DEFAULT 100
+);
+
+-- SQLite can't drop the inline UNIQUE on name, so rebuild users with names unique per domain.
+-- Users without a domain belong to the server's primary domain.
+CREATE TABLE users_new
+(
+    id            INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    pubkey        VARCHAR(66)  NOT NULL,
+    name          VARCHAR(255) NOT NULL,
+    disabled_zaps BOOLEAN      NOT NULL DEFAULT FALSE,
+    disabled      BOOLEAN      NOT NULL DEFAULT FALSE,
+    domain_id     INTEGER REFERENCES domains (id)
+);
+
+INSERT INTO users_new (id, pubkey, name, disabled_zaps, disabled)
+SELECT id, pubkey, name, disabled_zaps, disabled
+FROM users;
+
+DROP TABLE users;
+ALTER TABLE users_new RENAME TO users;
+
+CREATE UNIQUE INDEX idx_user_name ON users (COALESCE(domain_id, 0), name);
+CREATE UNIQUE INDEX idx_user_pk ON users (COALESCE(domain_id, 0), pubkey);
+
+PRAGMA foreign_key_check;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
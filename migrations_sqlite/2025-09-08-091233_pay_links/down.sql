@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_invoice_pay_link_id;
+
+ALTER TABLE invoice
+    DROP COLUMN pay_link_id;
+
+DROP TABLE IF EXISTS pay_links;
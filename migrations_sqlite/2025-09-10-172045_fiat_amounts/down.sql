@@ -0,0 +1,11 @@
+ALTER TABLE invoice
+    DROP COLUMN fiat_rate;
+ALTER TABLE invoice
+    DROP COLUMN fiat_amount;
+ALTER TABLE invoice
+    DROP COLUMN fiat_currency;
+
+ALTER TABLE pay_links
+    DROP COLUMN fiat_amount;
+ALTER TABLE pay_links
+    DROP COLUMN fiat_currency;
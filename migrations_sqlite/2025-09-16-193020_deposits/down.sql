@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS deposits;
+
+ALTER TABLE users
+    DROP COLUMN deposit_address;
@@ -0,0 +1,17 @@
+ALTER TABLE users
+    ADD COLUMN deposit_address VARCHAR(128);
+
+CREATE TABLE deposits
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id     INTEGER      NOT NULL REFERENCES users (id),
+    address     VARCHAR(128) NOT NULL,
+    txid        VARCHAR(64)  NOT NULL,
+    vout        INTEGER      NOT NULL,
+    amount_sats BIGINT       NOT NULL,
+    state       INTEGER      NOT NULL DEFAULT 0,
+    created_at  TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_deposits_outpoint ON deposits (txid, vout);
+CREATE INDEX idx_deposits_state ON deposits (state);
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_invoice_dedupe_key;
+
+ALTER TABLE invoice
+    DROP COLUMN dedupe_key;
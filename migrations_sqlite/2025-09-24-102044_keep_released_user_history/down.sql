@@ -0,0 +1,84 @@
+PRAGMA foreign_keys = OFF;
+
+BEGIN;
+
+CREATE TABLE invoice_new
+(
+    id             INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id        INTEGER       NOT NULL REFERENCES users (id),
+    bolt11         VARCHAR(2048) NOT NULL,
+    amount_msats   BIGINT        NOT NULL,
+    preimage       VARCHAR(64)   NOT NULL,
+    lnurlp_comment VARCHAR(100),
+    state          INTEGER       NOT NULL DEFAULT 0,
+    receive_id     VARCHAR(255),
+    pay_link_id    INTEGER REFERENCES pay_links (id),
+    fiat_currency  VARCHAR(3),
+    fiat_amount    BIGINT,
+    fiat_rate      DOUBLE,
+    dedupe_key     VARCHAR(64),
+    payment_hash   VARCHAR(64)
+);
+
+INSERT INTO invoice_new (id, user_id, bolt11, amount_msats, preimage, lnurlp_comment, state, receive_id, pay_link_id, fiat_currency, fiat_amount, fiat_rate, dedupe_key, payment_hash)
+SELECT id, user_id, bolt11, amount_msats, preimage, lnurlp_comment, state, receive_id, pay_link_id, fiat_currency, fiat_amount, fiat_rate, dedupe_key, payment_hash
+FROM invoice;
+
+DROP TABLE invoice;
+ALTER TABLE invoice_new RENAME TO invoice;
+
+CREATE INDEX idx_invoice_state ON invoice (state);
+CREATE INDEX idx_invoice_receive_id ON invoice (receive_id);
+CREATE INDEX idx_invoice_pay_link_id ON invoice (pay_link_id);
+CREATE INDEX idx_invoice_payment_hash ON invoice (payment_hash);
+CREATE UNIQUE INDEX idx_invoice_pending_dedupe_key ON invoice (user_id, dedupe_key) WHERE state = 0;
+
+CREATE TABLE pay_links_new
+(
+    id            INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id       INTEGER      NOT NULL REFERENCES users (id),
+    slug          VARCHAR(64)  NOT NULL,
+    description   VARCHAR(255) NOT NULL,
+    amount_msats  BIGINT       NOT NULL,
+    disabled      BOOLEAN      NOT NULL DEFAULT FALSE,
+    created_at    TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    fiat_currency VARCHAR(3),
+    fiat_amount   BIGINT
+);
+
+INSERT INTO pay_links_new (id, user_id, slug, description, amount_msats, disabled, created_at, fiat_currency, fiat_amount)
+SELECT id, user_id, slug, description, amount_msats, disabled, created_at, fiat_currency, fiat_amount
+FROM pay_links;
+
+DROP TABLE pay_links;
+ALTER TABLE pay_links_new RENAME TO pay_links;
+
+CREATE UNIQUE INDEX idx_pay_links_user_slug ON pay_links (user_id, slug);
+
+CREATE TABLE deposits_new
+(
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    user_id     INTEGER      NOT NULL REFERENCES users (id),
+    address     VARCHAR(128) NOT NULL,
+    txid        VARCHAR(64)  NOT NULL,
+    vout        INTEGER      NOT NULL,
+    amount_sats BIGINT       NOT NULL,
+    state       INTEGER      NOT NULL DEFAULT 0,
+    created_at  TIMESTAMP    NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO deposits_new (id, user_id, address, txid, vout, amount_sats, state, created_at)
+SELECT id, user_id, address, txid, vout, amount_sats, state, created_at
+FROM deposits;
+
+DROP TABLE deposits;
+ALTER TABLE deposits_new RENAME TO deposits;
+
+CREATE UNIQUE INDEX idx_deposits_outpoint ON deposits (txid, vout);
+CREATE INDEX idx_deposits_state ON deposits (state);
+
+PRAGMA foreign_key_check;
+
+COMMIT;
+
+PRAGMA foreign_keys = ON;
@@ -0,0 +1,609 @@
+use crate::bip353;
+use crate::domains::{lookup_domain, normalize_domain, primary_domain};
+use crate::error::ApiError;
+use crate::models::banned_name::BannedName;
+use crate::models::deposit::{Deposit, DepositState};
+use crate::models::domain::{Domain, NewDomain};
+use crate::models::invoice::{Invoice, InvoiceState};
+use crate::models::pay_link::{NewPayLink, PayLink};
+use crate::models::user::{User, UserFilter};
+use crate::models::zap::Zap;
+use crate::nip98;
+use crate::State;
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Path, Query};
+use axum::http::header;
+use axum::http::request::Parts;
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Router for the operator API, nested under `/admin`.
+///
+/// Every handler takes an [`AdminAuth`] so requests without a valid admin
+/// token or NIP-98 event from an allow-listed key are rejected.
+pub fn admin_router() -> Router {
+    Router::new()
+        .route("/users", get(list_users))
+        .route("/users/:name", get(get_user))
+        .route("/users/:name/disable", post(disable_user))
+        .route("/users/:name/enable", post(enable_user))
+        .route("/users/:name/release", post(release_user))
+        .route(
+            "/users/:name/links",
+            get(list_pay_links).post(create_pay_link),
+        )
+        .route("/links/:id/disable", post(disable_pay_link))
+        .route("/links/:id/enable", post(enable_pay_link))
+        .route("/domains", get(list_domains).post(add_domain))
+        .route("/domains/:domain", delete(remove_domain))
+        .route("/banned", get(list_banned))
+        .route("/banned/:name", post(ban_name).delete(unban_name))
+        .route("/invoices", get(list_invoices))
+        .route("/deposits", get(list_deposits))
+        .route("/zaps", get(list_zaps))
+        .route("/stats", get(stats))
+        .route("/bip353", get(bip353_zone))
+}
+
+/// Extractor that only succeeds for authenticated operators.
+///
+/// Accepts either `Authorization: Bearer <admin token>` or a NIP-98
+/// `Authorization: Nostr <base64 event>` signed by one of the configured admin pubkeys.
+pub struct AdminAuth;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminAuth
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let state = parts
+            .extensions
+            .get::<State>()
+            .cloned()
+            .ok_or(ApiError::Unauthorized)?;
+
+        let auth = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_owned)
+            .ok_or(ApiError::Unauthorized)?;
+
+        if let Some(token) = auth.strip_prefix("Bearer ") {
+            return match state.admin_token.as_deref() {
+                Some(expected) if constant_time_eq(expected.as_bytes(), token.as_bytes()) => {
+                    Ok(AdminAuth)
+                }
+                _ => Err(ApiError::Unauthorized),
+            };
+        }
+
+        if let Some(encoded) = auth.strip_prefix("Nostr ") {
+            return match nip98::verify_request(&state, parts, encoded).await {
+                Ok(pubkey) if state.admin_pubkeys.contains(&pubkey) => Ok(AdminAuth),
+                Ok(pubkey) => {
+                    tracing::warn!("Rejected NIP-98 admin auth: {pubkey} is not an admin");
+                    Err(ApiError::Unauthorized)
+                }
+                Err(e) => {
+                    tracing::warn!("Rejected NIP-98 admin auth: {e}");
+                    Err(ApiError::Unauthorized)
+                }
+            };
+        }
+
+        Err(ApiError::Unauthorized)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UserSearchParams {
+    /// Case-insensitive substring matched against the name, or an exact pubkey
+    pub q: Option<String>,
+    /// Only list users of this domain
+    pub domain: Option<String>,
+}
+
+/// Selects which domain a `/users/:name` request refers to, the primary domain if unset.
+#[derive(Debug, Default, Deserialize)]
+pub struct DomainParams {
+    pub domain: Option<String>,
+}
+
+/// Maps an optional domain name to its id, `None` being the primary domain.
+async fn domain_id(state: &State, domain: Option<&str>) -> Result<Option<i32>, ApiError> {
+    match domain {
+        None => Ok(None),
+        Some(domain) => Ok(lookup_domain(state, domain)
+            .await?
+            .ok_or(ApiError::DomainNotFound)?
+            .id),
+    }
+}
+
+pub async fn list_users(
+    _: AdminAuth,
+    Query(params): Query<UserSearchParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<User>>, ApiError> {
+    let domain_id = match params.domain.as_deref() {
+        Some(domain) => Some(domain_id(&state, Some(domain)).await?),
+        None => None,
+    };
+    let users = state
+        .storage
+        .search_users(UserFilter {
+            domain_id,
+            query: params.q,
+        })
+        .await?;
+
+    Ok(Json(users))
+}
+
+pub async fn get_user(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<User>, ApiError> {
+    let domain_id = domain_id(&state, params.domain.as_deref()).await?;
+    let user = state
+        .storage
+        .get_user_by_name(domain_id, &name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+
+    Ok(Json(user))
+}
+
+async fn set_user_disabled(
+    state: &State,
+    domain: Option<&str>,
+    name: &str,
+    disabled: bool,
+) -> Result<Json<Value>, ApiError> {
+    let domain_id = domain_id(state, domain).await?;
+    let user = state
+        .storage
+        .get_user_by_name(domain_id, name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    state.storage.set_user_disabled(&user, disabled).await?;
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+pub async fn disable_user(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    set_user_disabled(&state, params.domain.as_deref(), &name, true).await
+}
+
+pub async fn enable_user(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    set_user_disabled(&state, params.domain.as_deref(), &name, false).await
+}
+
+/// Deletes the user that owns `name` so it can be registered again, keeping their
+/// invoices, zaps, pay links and deposits.
+pub async fn release_user(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    let domain_id = domain_id(&state, params.domain.as_deref()).await?;
+    let user = state
+        .storage
+        .get_user_by_name(domain_id, &name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    state.storage.delete_user(&user).await?;
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PayLinkReport {
+    #[serde(flatten)]
+    pub link: PayLink,
+    pub settled_invoices: i64,
+    pub settled_volume_msats: i64,
+}
+
+/// Lists a user's pay links along with what has been paid through each.
+pub async fn list_pay_links(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<PayLinkReport>>, ApiError> {
+    let domain_id = domain_id(&state, params.domain.as_deref()).await?;
+    let user = state
+        .storage
+        .get_user_by_name(domain_id, &name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    let links = state.storage.get_pay_links(user.id).await?;
+
+    let mut reports = Vec::with_capacity(links.len());
+    for link in links {
+        let (settled_invoices, settled_volume_msats) =
+            state.storage.get_pay_link_totals(&link).await?;
+        reports.push(PayLinkReport {
+            link,
+            settled_invoices,
+            settled_volume_msats,
+        });
+    }
+
+    Ok(Json(reports))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePayLinkRequest {
+    pub slug: String,
+    pub description: String,
+    /// Price in millisatoshis, or set `fiat_currency` and `fiat_amount` instead
+    pub amount_msats: Option<u64>,
+    pub fiat_currency: Option<String>,
+    /// Price in the currency's smallest unit, e.g. cents
+    pub fiat_amount: Option<u64>,
+}
+
+/// Creates a fixed-amount pay link served at `/.well-known/lnurlp/{name}/{slug}`,
+/// priced either in millisatoshis or in one of the configured fiat currencies.
+pub async fn create_pay_link(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Query(params): Query<DomainParams>,
+    Extension(state): Extension<State>,
+    Json(req): Json<CreatePayLinkRequest>,
+) -> Result<Json<PayLink>, ApiError> {
+    let domain = match params.domain.as_deref() {
+        Some(domain) => lookup_domain(&state, domain)
+            .await?
+            .ok_or(ApiError::DomainNotFound)?,
+        None => primary_domain(&state),
+    };
+    let user = state
+        .storage
+        .get_user_by_name(domain.id, &name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+
+    let valid_slug = !req.slug.is_empty()
+        && req.slug.len() <= 64
+        && req
+            .slug
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_slug {
+        return Err(ApiError::InvalidRequest(
+            "Slug must be 1-64 letters, digits, dashes or underscores".to_string(),
+        ));
+    }
+    if req.description.is_empty() || req.description.chars().count() > 255 {
+        return Err(ApiError::InvalidRequest(
+            "Description must be 1-255 characters".to_string(),
+        ));
+    }
+
+    let (amount_msats, fiat_currency, fiat_amount) =
+        match (req.amount_msats, req.fiat_currency, req.fiat_amount) {
+            (Some(msats), None, None) if msats % 1_000 != 0 => {
+                return Err(ApiError::InvalidRequest(
+                    "amount_msats must be a whole number of sats".to_string(),
+                ))
+            }
+            (Some(msats), None, None) => (msats, None, None),
+            (None, Some(code), Some(amount)) => {
+                let currency = state
+                    .currencies
+                    .iter()
+                    .find(|c| c.code.eq_ignore_ascii_case(&code))
+                    .ok_or(ApiError::UnsupportedCurrency)?;
+                let btc_price = state
+                    .prices
+                    .btc_price(&currency.code)
+                    .await
+                    .map_err(ApiError::PriceUnavailable)?;
+                (
+                    currency.to_msats(amount, btc_price),
+                    Some(currency.code.clone()),
+                    Some(amount as i64),
+                )
+            }
+            _ => {
+                return Err(ApiError::InvalidRequest(
+                    "Set either amount_msats or fiat_currency and fiat_amount".to_string(),
+                ))
+            }
+        };
+    if amount_msats < domain.min_sendable || amount_msats > domain.max_sendable {
+        return Err(ApiError::AmountOutOfBounds);
+    }
+
+    let link = state
+        .storage
+        .insert_pay_link(NewPayLink {
+            user_id: user.id,
+            slug: req.slug,
+            description: req.description,
+            amount_msats: amount_msats as i64,
+            fiat_currency,
+            fiat_amount,
+        })
+        .await?;
+
+    Ok(Json(link))
+}
+
+async fn set_pay_link_disabled(
+    state: &State,
+    id: i32,
+    disabled: bool,
+) -> Result<Json<Value>, ApiError> {
+    let link = state
+        .storage
+        .get_pay_link(id)
+        .await?
+        .ok_or(ApiError::PayLinkNotFound)?;
+    state.storage.set_pay_link_disabled(&link, disabled).await?;
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+pub async fn disable_pay_link(
+    _: AdminAuth,
+    Path(id): Path<i32>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    set_pay_link_disabled(&state, id, true).await
+}
+
+pub async fn enable_pay_link(
+    _: AdminAuth,
+    Path(id): Path<i32>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    set_pay_link_disabled(&state, id, false).await
+}
+
+pub async fn list_domains(
+    _: AdminAuth,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<Domain>>, ApiError> {
+    let domains = state.storage.get_domains().await?;
+
+    Ok(Json(domains))
+}
+
+/// Starts hosting lightning addresses under a new domain.
+pub async fn add_domain(
+    _: AdminAuth,
+    Extension(state): Extension<State>,
+    Json(mut new_domain): Json<NewDomain>,
+) -> Result<Json<Domain>, ApiError> {
+    new_domain.domain = normalize_domain(&new_domain.domain);
+    if new_domain.domain.is_empty() {
+        return Err(ApiError::InvalidRequest("Domain is required".to_string()));
+    }
+    if new_domain.domain == normalize_domain(&state.domain) {
+        return Err(ApiError::InvalidRequest(
+            "Domain is already the primary domain".to_string(),
+        ));
+    }
+    if new_domain.min_sendable < 1 || new_domain.min_sendable > new_domain.max_sendable {
+        return Err(ApiError::InvalidRequest(
+            "Invalid sendable range".to_string(),
+        ));
+    }
+    if !(0..=100).contains(&new_domain.comment_allowed) {
+        return Err(ApiError::InvalidRequest(
+            "comment_allowed must be between 0 and 100".to_string(),
+        ));
+    }
+
+    let domain = state.storage.insert_domain(new_domain).await?;
+
+    Ok(Json(domain))
+}
+
+/// Stops hosting a domain, fails while users are still registered under it.
+pub async fn remove_domain(
+    _: AdminAuth,
+    Path(domain): Path<String>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    let domain = state
+        .storage
+        .get_domain(&normalize_domain(&domain))
+        .await?
+        .ok_or(ApiError::DomainNotFound)?;
+
+    if state.storage.count_domain_users(&domain).await? > 0 {
+        return Err(ApiError::DomainInUse);
+    }
+
+    state.storage.delete_domain(&domain).await?;
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+pub async fn list_banned(
+    _: AdminAuth,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<BannedName>>, ApiError> {
+    let names = state.storage.get_banned_names().await?;
+
+    Ok(Json(names))
+}
+
+/// Bans `name` from registration on every domain, disabling its current owners.
+pub async fn ban_name(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    state.storage.ban_name(&name).await?;
+    for user in state.storage.get_users_by_name(&name).await? {
+        state.storage.set_user_disabled(&user, true).await?;
+    }
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+pub async fn unban_name(
+    _: AdminAuth,
+    Path(name): Path<String>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Value>, ApiError> {
+    state.storage.unban_name(&name).await?;
+
+    Ok(Json(json!({ "status": "OK" })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct InvoiceListParams {
+    pub state: Option<InvoiceState>,
+}
+
+pub async fn list_invoices(
+    _: AdminAuth,
+    Query(params): Query<InvoiceListParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<Invoice>>, ApiError> {
+    let invoices = match params.state {
+        Some(s) => state.storage.get_invoices_by_state(s as i32).await,
+        None => state.storage.get_invoices().await,
+    }?;
+
+    Ok(Json(invoices))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DepositListParams {
+    pub state: Option<DepositState>,
+}
+
+pub async fn list_deposits(
+    _: AdminAuth,
+    Query(params): Query<DepositListParams>,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<Deposit>>, ApiError> {
+    let deposits = state
+        .storage
+        .get_deposits()
+        .await?
+        .into_iter()
+        .filter(|d| params.state.is_none_or(|s| d.state == s as i32))
+        .collect();
+
+    Ok(Json(deposits))
+}
+
+pub async fn list_zaps(
+    _: AdminAuth,
+    Extension(state): Extension<State>,
+) -> Result<Json<Vec<Zap>>, ApiError> {
+    let zaps = state.storage.get_zaps().await?;
+
+    Ok(Json(zaps))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZoneParams {
+    pub domain: Option<String>,
+    #[serde(default = "default_zone_ttl")]
+    pub ttl: u32,
+}
+
+fn default_zone_ttl() -> u32 {
+    3600
+}
+
+/// BIP-353 TXT records for every user as a zone file fragment, for syncing to a signed zone.
+pub async fn bip353_zone(
+    _: AdminAuth,
+    Query(params): Query<ZoneParams>,
+    Extension(state): Extension<State>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), ApiError> {
+    let zone = bip353::zone_fragment(
+        state.storage.as_ref(),
+        &state.public_url,
+        &state.domain,
+        params.domain.as_deref(),
+        params.ttl,
+    )
+    .await?;
+
+    Ok(([(header::CONTENT_TYPE, "text/plain")], zone))
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct AdminStats {
+    pub users: i64,
+    pub disabled_users: i64,
+    pub pending_invoices: i64,
+    pub settled_invoices: i64,
+    pub cancelled_invoices: i64,
+    pub settled_volume_msats: i64,
+    pub zaps: i64,
+}
+
+/// Aggregate counts and settled volume across all users.
+pub async fn stats(
+    _: AdminAuth,
+    Extension(state): Extension<State>,
+) -> Result<Json<AdminStats>, ApiError> {
+    let (users, disabled_users) = state.storage.count_users().await?;
+    let mut stats = AdminStats {
+        users,
+        disabled_users,
+        zaps: state.storage.count_zaps().await?,
+        ..Default::default()
+    };
+    for (invoice_state, count, amount_msats) in state.storage.get_invoice_totals().await? {
+        if invoice_state == InvoiceState::Pending as i32 {
+            stats.pending_invoices = count;
+        } else if invoice_state == InvoiceState::Settled as i32 {
+            stats.settled_invoices = count;
+            stats.settled_volume_msats = amount_msats;
+        } else if invoice_state == InvoiceState::Cancelled as i32 {
+            stats.cancelled_invoices = count;
+        }
+    }
+
+    Ok(Json(stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_token_comparison() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
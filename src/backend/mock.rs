@@ -0,0 +1,134 @@
+use crate::backend::{
+    BackendError, BackendEvent, ConnectionState, CreatedInvoice, DepositOutput, InvoiceBackend,
+    PaymentStatus,
+};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{Address, CompressedPublicKey, Network};
+use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+struct MockPayment {
+    preimage: [u8; 32],
+    created_at: Instant,
+}
+
+/// In-memory [`InvoiceBackend`] for development and testing.
+///
+/// Issues real signed bolt11 invoices from a throwaway node key and treats
+/// every invoice as paid once `settle_after` has passed. Deposit addresses are
+/// derived from the receiver's key but no deposits are ever seen.
+pub struct MockBackend {
+    network: Network,
+    currency: Currency,
+    node_key: SecretKey,
+    settle_after: Duration,
+    counter: AtomicU64,
+    payments: Mutex<HashMap<String, MockPayment>>,
+    events: broadcast::Sender<BackendEvent>,
+}
+
+impl MockBackend {
+    pub fn new(network: Network, settle_after: Duration) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let node_key =
+            SecretKey::from_slice(sha256::Hash::hash(&seed.to_be_bytes()).as_byte_array())
+                .expect("sha256 output is a valid secret key");
+
+        let (events, _) = broadcast::channel(16);
+
+        Self {
+            network,
+            currency: Currency::from(network),
+            node_key,
+            settle_after,
+            counter: AtomicU64::new(0),
+            payments: Mutex::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Derives a unique preimage per invoice from the node key.
+    fn next_preimage(&self) -> [u8; 32] {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+        let mut engine = self.node_key.secret_bytes().to_vec();
+        engine.extend_from_slice(&n.to_be_bytes());
+        sha256::Hash::hash(&engine).to_byte_array()
+    }
+}
+
+#[async_trait::async_trait]
+impl InvoiceBackend for MockBackend {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description_hash: sha256::Hash,
+        _receiver: PublicKey,
+    ) -> Result<CreatedInvoice, BackendError> {
+        let preimage = self.next_preimage();
+        let payment_hash = sha256::Hash::hash(&preimage);
+
+        let secp = Secp256k1::new();
+        let bolt11 = InvoiceBuilder::new(self.currency.clone())
+            .description_hash(description_hash)
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(self.next_preimage()))
+            .current_timestamp()
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(amount_msats)
+            .build_signed(|msg| secp.sign_ecdsa_recoverable(msg, &self.node_key))
+            .map_err(|e| BackendError(e.to_string()))?;
+
+        let id = payment_hash.to_string();
+        self.payments.lock().unwrap().insert(
+            id.clone(),
+            MockPayment {
+                preimage,
+                created_at: Instant::now(),
+            },
+        );
+
+        Ok(CreatedInvoice {
+            id,
+            bolt11,
+            preimage: Some(hex::encode(preimage)),
+        })
+    }
+
+    async fn deposit_address(&self, receiver: PublicKey) -> Result<String, BackendError> {
+        Ok(Address::p2wpkh(&CompressedPublicKey(receiver), self.network).to_string())
+    }
+
+    async fn deposits(&self, _address: &str) -> Result<Vec<DepositOutput>, BackendError> {
+        Ok(vec![])
+    }
+
+    async fn payment_status(&self, id: &str) -> Result<PaymentStatus, BackendError> {
+        let payments = self.payments.lock().unwrap();
+        Ok(match payments.get(id) {
+            Some(p) if p.created_at.elapsed() >= self.settle_after => PaymentStatus::Paid {
+                preimage: Some(hex::encode(p.preimage)),
+            },
+            _ => PaymentStatus::Pending,
+        })
+    }
+
+    fn subscribe_events(&self) -> broadcast::Receiver<BackendEvent> {
+        self.events.subscribe()
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        // the mock is always connected and synced
+        ConnectionState {
+            connected: true,
+            synced: true,
+        }
+    }
+}
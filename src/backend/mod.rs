@@ -0,0 +1,147 @@
+use bitcoin::hashes::sha256;
+use bitcoin::secp256k1::PublicKey;
+use lightning_invoice::Bolt11Invoice;
+use std::fmt::{Display, Formatter};
+use tokio::sync::broadcast;
+
+pub mod mock;
+pub mod spark;
+
+/// Something that can issue invoices on behalf of users and tell us when they are paid.
+///
+/// The server only talks to the payment layer through this trait so that the
+/// routes and invoice subscriber can run against [`mock::MockBackend`] without a Spark operator.
+#[async_trait::async_trait]
+pub trait InvoiceBackend: Send + Sync {
+    /// Creates an invoice for `amount_msats` committing to `description_hash`,
+    /// paying out to the wallet identified by `receiver`.
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description_hash: sha256::Hash,
+        receiver: PublicKey,
+    ) -> Result<CreatedInvoice, BackendError>;
+
+    /// The Spark address of the wallet identified by `receiver`, for native Spark transfers.
+    async fn spark_address(&self, _receiver: PublicKey) -> Result<String, BackendError> {
+        Err(BackendError::unsupported())
+    }
+
+    /// A static on-chain address paying into the wallet identified by `receiver`,
+    /// offered as a fallback for amounts too large for lightning.
+    async fn deposit_address(&self, _receiver: PublicKey) -> Result<String, BackendError> {
+        Err(BackendError::unsupported())
+    }
+
+    /// Lists outputs paid to a deposit address from [`InvoiceBackend::deposit_address`].
+    async fn deposits(&self, _address: &str) -> Result<Vec<DepositOutput>, BackendError> {
+        Err(BackendError::unsupported())
+    }
+
+    /// Balance of the backend's own wallet in sats.
+    async fn balance(&self) -> Result<u64, BackendError> {
+        Err(BackendError::unsupported())
+    }
+
+    /// Looks up the status of an invoice by the id returned from [`InvoiceBackend::create_invoice`].
+    async fn payment_status(&self, id: &str) -> Result<PaymentStatus, BackendError>;
+
+    /// Subscribes to connection and sync events from the backend.
+    fn subscribe_events(&self) -> broadcast::Receiver<BackendEvent>;
+
+    /// Connection and sync state as of the latest event, for subscribers that
+    /// may have missed the events sent before they subscribed.
+    fn connection_state(&self) -> ConnectionState;
+}
+
+#[derive(Debug, Clone)]
+pub struct CreatedInvoice {
+    /// Backend specific id used to look the payment up later
+    pub id: String,
+    pub bolt11: Bolt11Invoice,
+    pub preimage: Option<String>,
+}
+
+/// An on-chain output paid to one of our users' deposit addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DepositOutput {
+    pub txid: String,
+    pub vout: u32,
+    pub amount_sats: u64,
+    pub status: DepositStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositStatus {
+    /// In the mempool or not yet deep enough to claim.
+    Unconfirmed,
+    /// Confirmed and claimable.
+    Confirmed,
+    /// Claimed into the receiver's wallet.
+    Claimed,
+    /// The claim failed.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentStatus {
+    /// Not paid yet, or not found.
+    Pending,
+    /// Paid and claimed by the receiver.
+    Paid { preimage: Option<String> },
+    /// The backend gave up on the payment.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendEvent {
+    Connected,
+    Disconnected,
+    Synced,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionState {
+    pub connected: bool,
+    /// Whether the backend has synced at least once
+    pub synced: bool,
+}
+
+impl ConnectionState {
+    /// Applies `event` on top of this state.
+    pub fn apply(&mut self, event: BackendEvent) {
+        match event {
+            BackendEvent::Connected => self.connected = true,
+            BackendEvent::Disconnected => self.connected = false,
+            BackendEvent::Synced => {
+                self.connected = true;
+                self.synced = true;
+            }
+        }
+    }
+}
+
+/// An error from the payment backend, kept distinct so callers can tell it apart
+/// from database and validation errors.
+#[derive(Debug)]
+pub struct BackendError(pub String);
+
+impl BackendError {
+    const UNSUPPORTED: &'static str = "Not supported by this backend";
+
+    pub fn unsupported() -> Self {
+        Self(Self::UNSUPPORTED.to_string())
+    }
+
+    pub fn is_unsupported(&self) -> bool {
+        self.0 == Self::UNSUPPORTED
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Backend error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BackendError {}
@@ -0,0 +1,152 @@
+use crate::backend::{
+    BackendError, BackendEvent, ConnectionState, CreatedInvoice, InvoiceBackend, PaymentStatus,
+};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::secp256k1::PublicKey;
+use lightning_invoice::Bolt11Invoice;
+use spark::services::InvoiceDescription;
+use spark::signer::DefaultSigner;
+use spark_wallet::{
+    LightningReceiveRequestStatus, Network, SparkAddress, SparkWallet, SparkWalletConfig,
+    WalletEvent,
+};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// [`InvoiceBackend`] backed by a Spark wallet, invoices are paid out through the Spark SSP.
+pub struct SparkBackend {
+    wallet: Arc<SparkWallet<DefaultSigner>>,
+    network: Network,
+    events: broadcast::Sender<BackendEvent>,
+    state: Arc<RwLock<ConnectionState>>,
+}
+
+impl SparkBackend {
+    pub async fn connect(config: SparkWalletConfig, seed: &[u8]) -> anyhow::Result<Self> {
+        let network = config.network;
+        let signer = DefaultSigner::new(seed, network)?;
+        let wallet = Arc::new(SparkWallet::connect(config, signer).await?);
+
+        // events sent while connecting are gone, but connecting means the operators were reachable
+        let state = Arc::new(RwLock::new(ConnectionState {
+            connected: true,
+            synced: false,
+        }));
+        let (events, _) = broadcast::channel(100);
+        tokio::spawn(forward_events(
+            wallet.subscribe_events(),
+            events.clone(),
+            state.clone(),
+        ));
+
+        Ok(Self {
+            wallet,
+            network,
+            events,
+            state,
+        })
+    }
+}
+
+/// Translates the wallet's own events into [`BackendEvent`]s, keeping track of the latest state.
+async fn forward_events(
+    mut wallet_events: broadcast::Receiver<WalletEvent>,
+    events: broadcast::Sender<BackendEvent>,
+    state: Arc<RwLock<ConnectionState>>,
+) {
+    loop {
+        let event = match wallet_events.recv().await {
+            Ok(WalletEvent::StreamConnected) => BackendEvent::Connected,
+            Ok(WalletEvent::StreamDisconnected) => BackendEvent::Disconnected,
+            Ok(WalletEvent::Synced) => BackendEvent::Synced,
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => {
+                warn!("Spark event forwarder lagged {n} events");
+                continue;
+            }
+            Err(RecvError::Closed) => {
+                state.write().unwrap().apply(BackendEvent::Disconnected);
+                let _ = events.send(BackendEvent::Disconnected);
+                break;
+            }
+        };
+        state.write().unwrap().apply(event);
+        // no subscribers is fine
+        let _ = events.send(event);
+    }
+}
+
+// `deposit_address`/`deposits` keep the unsupported default, the Spark SDK can only generate
+// static deposit addresses for the connected wallet.
+#[async_trait::async_trait]
+impl InvoiceBackend for SparkBackend {
+    async fn create_invoice(
+        &self,
+        amount_msats: u64,
+        description_hash: sha256::Hash,
+        receiver: PublicKey,
+    ) -> Result<CreatedInvoice, BackendError> {
+        let resp = self
+            .wallet
+            .create_lightning_invoice(
+                amount_msats / 1_000, // todo they dont support msats
+                Some(InvoiceDescription::DescriptionHash(
+                    description_hash.to_byte_array(),
+                )),
+                Some(receiver),
+            )
+            .await
+            .map_err(|e| BackendError(e.to_string()))?;
+
+        let bolt11 =
+            Bolt11Invoice::from_str(&resp.invoice).map_err(|e| BackendError(e.to_string()))?;
+
+        Ok(CreatedInvoice {
+            id: resp.id,
+            bolt11,
+            preimage: resp.payment_preimage,
+        })
+    }
+
+    async fn spark_address(&self, receiver: PublicKey) -> Result<String, BackendError> {
+        Ok(SparkAddress::new(receiver, self.network, None).to_string())
+    }
+
+    async fn balance(&self) -> Result<u64, BackendError> {
+        self.wallet
+            .get_balance()
+            .await
+            .map_err(|e| BackendError(e.to_string()))
+    }
+
+    async fn payment_status(&self, id: &str) -> Result<PaymentStatus, BackendError> {
+        let payment = self
+            .wallet
+            .fetch_lightning_receive_payment(id)
+            .await
+            .map_err(|e| BackendError(e.to_string()))?;
+
+        Ok(match payment {
+            Some(p) if p.status == LightningReceiveRequestStatus::TransferCompleted => {
+                PaymentStatus::Paid {
+                    preimage: p.payment_preimage,
+                }
+            }
+            Some(p) if p.status == LightningReceiveRequestStatus::TransferFailed => {
+                PaymentStatus::Failed
+            }
+            _ => PaymentStatus::Pending,
+        })
+    }
+
+    fn subscribe_events(&self) -> broadcast::Receiver<BackendEvent> {
+        self.events.subscribe()
+    }
+
+    fn connection_state(&self) -> ConnectionState {
+        *self.state.read().unwrap()
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config::PublicUrl;
+use crate::domains::normalize_domain;
+use crate::storage::Storage;
+use lnurl::lnurl::LnUrl;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Max length of a single character-string in a TXT record.
+const TXT_CHUNK_LEN: usize = 255;
+
+/// BIP-21 URI with the user's LNURL, the payment instruction BIP-353 resolves to.
+pub fn payment_instruction(lnurl: &str) -> String {
+    format!("bitcoin:?lightning={lnurl}")
+}
+
+/// A zone file line publishing `instruction` for `name@domain`.
+pub fn txt_record(name: &str, domain: &str, instruction: &str, ttl: u32) -> String {
+    // long values have to be split into multiple strings, which resolvers concatenate
+    let value = instruction
+        .as_bytes()
+        .chunks(TXT_CHUNK_LEN)
+        .map(|c| format!("\"{}\"", String::from_utf8_lossy(c)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!("{name}.user._bitcoin-payment.{domain}. {ttl} IN TXT {value}")
+}
+
+/// Whether `name` can be used as a DNS label.
+fn is_dns_label(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Generates a zone file fragment with a BIP-353 TXT record for every active user,
+/// optionally limited to a single domain.
+///
+/// Names that aren't valid DNS labels are left out with a comment.
+pub async fn zone_fragment(
+    storage: &dyn Storage,
+    public_url: &PublicUrl,
+    primary_domain: &str,
+    only_domain: Option<&str>,
+    ttl: u32,
+) -> anyhow::Result<String> {
+    let domains: HashMap<i32, String> = storage
+        .get_domains()
+        .await?
+        .into_iter()
+        .map(|d| (d.id, d.domain))
+        .collect();
+    let only_domain = only_domain.map(normalize_domain);
+
+    let mut zone = String::new();
+    for user in storage.get_users().await? {
+        if user.disabled {
+            continue;
+        }
+
+        let (domain, host) = match user.domain_id {
+            None => (primary_domain, public_url.host.as_str()),
+            Some(id) => match domains.get(&id) {
+                Some(domain) => (domain.as_str(), domain.as_str()),
+                None => continue,
+            },
+        };
+        let domain = normalize_domain(domain);
+        if only_domain.as_ref().is_some_and(|d| *d != domain) {
+            continue;
+        }
+
+        if !is_dns_label(&user.name) {
+            writeln!(
+                zone,
+                "; skipped {}@{domain}, not a valid DNS label",
+                user.name
+            )?;
+            continue;
+        }
+
+        let url = public_url.join(host, &format!("/.well-known/lnurlp/{}", user.name));
+        let lnurl = LnUrl::from_url(url).encode().to_uppercase();
+        let instruction = payment_instruction(&lnurl);
+        writeln!(
+            zone,
+            "{}",
+            txt_record(&user.name, &domain, &instruction, ttl)
+        )?;
+    }
+
+    Ok(zone)
+}
@@ -0,0 +1,163 @@
+use crate::backend::InvoiceBackend;
+use crate::config::{InvoiceCommand, UserCommand, WalletCommand, ZapCommand};
+use crate::domains::normalize_domain;
+use crate::invoice_subscriber::publish_zap_receipt;
+use crate::models::invoice::InvoiceState;
+use crate::models::user::{NewUser, User, UserFilter};
+use crate::storage::Storage;
+use crate::State;
+use anyhow::{anyhow, bail};
+use bitcoin::secp256k1::PublicKey;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// Runs a `user` subcommand, `primary_domain` is the configured `--domain`.
+pub async fn run_user_command(
+    storage: &dyn Storage,
+    primary_domain: &str,
+    cmd: &UserCommand,
+) -> anyhow::Result<()> {
+    match cmd {
+        UserCommand::Add {
+            name,
+            pubkey,
+            domain,
+        } => {
+            let pubkey = PublicKey::from_str(pubkey)?;
+            let domain_id = domain_id(storage, primary_domain, domain.as_deref()).await?;
+            if storage.get_user_by_name(domain_id, name).await?.is_some() {
+                bail!("Name {name} is taken");
+            }
+
+            let user = storage
+                .insert_user(NewUser {
+                    pubkey: pubkey.to_string(),
+                    name: name.to_lowercase(),
+                    domain_id,
+                })
+                .await?;
+            print_json(&user)?;
+        }
+        UserCommand::List { domain } => {
+            let domain_id = match domain {
+                Some(domain) => Some(domain_id(storage, primary_domain, Some(domain)).await?),
+                None => None,
+            };
+            let users = storage
+                .search_users(UserFilter {
+                    domain_id,
+                    query: None,
+                })
+                .await?;
+            print_json(&users)?;
+        }
+        UserCommand::Disable { name, domain } => {
+            let user = find_user(storage, primary_domain, name, domain.as_deref()).await?;
+            storage.set_user_disabled(&user, true).await?;
+            println!("Disabled {name}");
+        }
+        UserCommand::Delete { name, domain } => {
+            let user = find_user(storage, primary_domain, name, domain.as_deref()).await?;
+            storage.delete_user(&user).await?;
+            println!("Deleted {name}");
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_invoice_command(
+    storage: &dyn Storage,
+    cmd: &InvoiceCommand,
+) -> anyhow::Result<()> {
+    match cmd {
+        InvoiceCommand::List { state } => {
+            let invoices = match state {
+                Some(s) => storage.get_invoices_by_state(*s as i32).await?,
+                None => storage.get_invoices().await?,
+            };
+            print_json(&invoices)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run_wallet_command(
+    wallet: &dyn InvoiceBackend,
+    cmd: &WalletCommand,
+) -> anyhow::Result<()> {
+    match cmd {
+        WalletCommand::Balance => println!("{} sats", wallet.balance().await?),
+        // handled before connecting to the wallet
+        WalletCommand::ExportLegacySeed { .. } => {}
+    }
+
+    Ok(())
+}
+
+/// Runs a `zap` subcommand, these need the zap signer so they get the full [`State`].
+pub async fn run_zap_command(state: &State, cmd: &ZapCommand) -> anyhow::Result<()> {
+    match cmd {
+        ZapCommand::Resend { id } => {
+            let invoice = state
+                .storage
+                .get_invoice(*id)
+                .await?
+                .ok_or(anyhow!("Invoice {id} not found"))?;
+            if invoice.state != InvoiceState::Settled as i32 {
+                bail!("Invoice {id} is not settled");
+            }
+            let zap = state
+                .storage
+                .get_zap(*id)
+                .await?
+                .ok_or(anyhow!("Invoice {id} has no zap request"))?;
+
+            let preimage = Some(invoice.preimage.clone()).filter(|p| !p.is_empty());
+            publish_zap_receipt(state, &invoice, &zap, preimage).await?;
+            println!("Published zap receipt for invoice {id}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Maps a `--domain` argument to a domain id, `None` being the primary domain.
+async fn domain_id(
+    storage: &dyn Storage,
+    primary_domain: &str,
+    domain: Option<&str>,
+) -> anyhow::Result<Option<i32>> {
+    let Some(domain) = domain.map(normalize_domain) else {
+        return Ok(None);
+    };
+    if domain == normalize_domain(primary_domain) {
+        return Ok(None);
+    }
+
+    let domain = storage
+        .get_domain(&domain)
+        .await?
+        .ok_or(anyhow!("Unknown domain {domain}"))?;
+    Ok(Some(domain.id))
+}
+
+async fn find_user(
+    storage: &dyn Storage,
+    primary_domain: &str,
+    name: &str,
+    domain: Option<&str>,
+) -> anyhow::Result<User> {
+    let domain_id = domain_id(storage, primary_domain, domain).await?;
+    storage
+        .get_user_by_name(domain_id, name)
+        .await?
+        .ok_or(anyhow!("User {name} not found"))
+}
+
+/// Prints `value` as the same JSON the admin API returns.
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
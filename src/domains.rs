@@ -0,0 +1,97 @@
+use crate::models::domain::Domain;
+use crate::State;
+
+/// LNURL-pay settings for the domain a lightning address lives under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainSettings {
+    /// `None` for the primary domain
+    pub id: Option<i32>,
+    pub domain: String,
+    pub min_sendable: u64,
+    pub max_sendable: u64,
+    pub comment_allowed: u32,
+}
+
+impl From<Domain> for DomainSettings {
+    fn from(d: Domain) -> Self {
+        Self {
+            id: Some(d.id),
+            domain: d.domain,
+            min_sendable: d.min_sendable as u64,
+            max_sendable: d.max_sendable as u64,
+            comment_allowed: d.comment_allowed as u32,
+        }
+    }
+}
+
+/// The domain configured with `--domain`, its settings come from the config.
+pub fn primary_domain(state: &State) -> DomainSettings {
+    let settings = state.settings.read().unwrap();
+    DomainSettings {
+        id: None,
+        domain: state.domain.clone(),
+        min_sendable: settings.min_sendable,
+        max_sendable: settings.max_sendable,
+        comment_allowed: settings.comment_allowed,
+    }
+}
+
+/// Absolute url for `path` on `domain`, using the scheme and path prefix of the public url.
+///
+/// The primary domain is served from the public url's host, which may differ from
+/// the domain in its lightning addresses, e.g. when it includes a port.
+pub fn domain_url(state: &State, domain: &DomainSettings, path: &str) -> String {
+    let host = match domain.id {
+        None => &state.public_url.host,
+        Some(_) => &domain.domain,
+    };
+    state.public_url.join(host, path)
+}
+
+/// Lowercases and strips the port and trailing dot so `Host` headers match stored domains.
+pub fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim();
+    let host = match domain.strip_prefix('[') {
+        // an IPv6 literal, the port comes after the closing bracket
+        Some(literal) => literal.split(']').next().unwrap_or(literal),
+        None => domain.split(':').next().unwrap_or(domain),
+    };
+    host.trim_end_matches('.').to_lowercase()
+}
+
+/// Finds the settings for `domain`, returning `None` if we don't host it.
+pub async fn lookup_domain(state: &State, domain: &str) -> anyhow::Result<Option<DomainSettings>> {
+    let domain = normalize_domain(domain);
+    if domain == normalize_domain(&state.domain) {
+        return Ok(Some(primary_domain(state)));
+    }
+
+    Ok(state.storage.get_domain(&domain).await?.map(Into::into))
+}
+
+/// Resolves the domain a request was made to from its `Host` header.
+///
+/// Unknown hosts, like requests made directly to the server's IP, fall back to the primary domain.
+pub async fn resolve_domain(state: &State, host: Option<&str>) -> anyhow::Result<DomainSettings> {
+    match host {
+        Some(host) => Ok(lookup_domain(state, host)
+            .await?
+            .unwrap_or_else(|| primary_domain(state))),
+        None => Ok(primary_domain(state)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_host_names() {
+        assert_eq!(normalize_domain("Example.COM"), "example.com");
+        assert_eq!(normalize_domain("example.com."), "example.com");
+        assert_eq!(normalize_domain(" example.com "), "example.com");
+        assert_eq!(normalize_domain("Example.com:8080"), "example.com");
+        assert_eq!(normalize_domain("example.com.:443"), "example.com");
+        assert_eq!(normalize_domain("[::1]:8080"), "::1");
+    }
+}
@@ -0,0 +1,286 @@
+use crate::backend::BackendError;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// An error returned from the HTTP API.
+///
+/// Every variant has a stable machine readable [`code`](ApiError::code) and an HTTP status,
+/// and renders as a LUD-06 `{"status": "ERROR", "reason", "code"}` body. For server side
+/// failures only a generic reason goes out, the details are logged.
+///
+/// Functions returning `anyhow::Result` can fail with an `ApiError` too, converting
+/// back with [`From<anyhow::Error>`] recovers it, anything else maps to a server error.
+#[derive(Debug)]
+pub enum ApiError {
+    MissingAmount,
+    AmountOutOfBounds,
+    CommentTooLong,
+    InvalidZapRequest,
+    UnsupportedCurrency,
+    UserNotFound,
+    UserDisabled,
+    ZapsDisabled,
+    PayLinkNotFound,
+    InvoiceNotFound,
+    DomainNotFound,
+    DomainInUse,
+    NameTaken,
+    /// The pubkey already has a name on the domain
+    PubkeyTaken,
+    NameBanned,
+    RouteNotFound,
+    /// A request that failed validation, the message is shown to the client
+    InvalidRequest(String),
+    Unauthorized,
+    RateLimited(Duration),
+    /// The user has too many event streams open
+    TooManyStreams,
+    PriceUnavailable(anyhow::Error),
+    Backend(BackendError),
+    Database(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl ApiError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::MissingAmount => "missing_amount",
+            Self::AmountOutOfBounds => "amount_out_of_bounds",
+            Self::CommentTooLong => "comment_too_long",
+            Self::InvalidZapRequest => "invalid_zap_request",
+            Self::UnsupportedCurrency => "unsupported_currency",
+            Self::UserNotFound => "user_not_found",
+            Self::UserDisabled => "user_disabled",
+            Self::ZapsDisabled => "zaps_disabled",
+            Self::PayLinkNotFound => "pay_link_not_found",
+            Self::InvoiceNotFound => "invoice_not_found",
+            Self::DomainNotFound => "domain_not_found",
+            Self::DomainInUse => "domain_in_use",
+            Self::NameTaken => "name_taken",
+            Self::PubkeyTaken => "pubkey_taken",
+            Self::NameBanned => "name_banned",
+            Self::RouteNotFound => "route_not_found",
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::Unauthorized => "unauthorized",
+            Self::RateLimited(_) => "rate_limited",
+            Self::TooManyStreams => "too_many_streams",
+            Self::PriceUnavailable(_) => "price_unavailable",
+            Self::Backend(_) => "wallet_error",
+            Self::Database(_) => "database_unavailable",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::MissingAmount
+            | Self::AmountOutOfBounds
+            | Self::CommentTooLong
+            | Self::InvalidZapRequest
+            | Self::UnsupportedCurrency
+            | Self::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            Self::UserNotFound
+            | Self::PayLinkNotFound
+            | Self::InvoiceNotFound
+            | Self::DomainNotFound
+            | Self::RouteNotFound => StatusCode::NOT_FOUND,
+            Self::UserDisabled | Self::ZapsDisabled | Self::NameBanned => StatusCode::FORBIDDEN,
+            Self::DomainInUse | Self::NameTaken | Self::PubkeyTaken => StatusCode::CONFLICT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::RateLimited(_) | Self::TooManyStreams => StatusCode::TOO_MANY_REQUESTS,
+            Self::Backend(_) => StatusCode::BAD_GATEWAY,
+            Self::PriceUnavailable(_) | Self::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The message shown to clients.
+    pub fn reason(&self) -> String {
+        match self {
+            Self::MissingAmount => "Missing amount parameter".to_string(),
+            Self::AmountOutOfBounds => "Amount out of bounds".to_string(),
+            Self::CommentTooLong => "Comment too long".to_string(),
+            Self::InvalidZapRequest => "Invalid zap request".to_string(),
+            Self::UnsupportedCurrency => "Unsupported currency".to_string(),
+            Self::UserNotFound => "User not found".to_string(),
+            Self::UserDisabled => "User is disabled".to_string(),
+            Self::ZapsDisabled => "Zaps are disabled for this user".to_string(),
+            Self::PayLinkNotFound => "Pay link not found".to_string(),
+            Self::InvoiceNotFound => "Invoice not found".to_string(),
+            Self::DomainNotFound => "Domain not found".to_string(),
+            Self::DomainInUse => "Domain still has users".to_string(),
+            Self::NameTaken => "Name is taken".to_string(),
+            Self::PubkeyTaken => "Pubkey already has a name".to_string(),
+            Self::NameBanned => "Name is not available".to_string(),
+            Self::RouteNotFound => "No such route".to_string(),
+            Self::InvalidRequest(reason) => reason.clone(),
+            Self::Unauthorized => "Unauthorized".to_string(),
+            Self::RateLimited(_) => "Too many requests".to_string(),
+            Self::TooManyStreams => "Too many open event streams".to_string(),
+            Self::PriceUnavailable(_) => "Exchange rate unavailable".to_string(),
+            Self::Backend(_) => "Wallet unavailable".to_string(),
+            Self::Database(_) => "Database unavailable".to_string(),
+            Self::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PriceUnavailable(e) | Self::Database(e) | Self::Internal(e) => {
+                write!(f, "{}: {e:#}", self.reason())
+            }
+            Self::Backend(e) => write!(f, "{e}"),
+            _ => write!(f, "{}", self.reason()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<ApiError>() {
+            Ok(e) => return e,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<BackendError>() {
+            Ok(e) => return Self::Backend(e),
+            Err(err) => err,
+        };
+
+        if err.downcast_ref::<diesel::result::Error>().is_some()
+            || err.downcast_ref::<diesel::r2d2::PoolError>().is_some()
+        {
+            return Self::Database(err);
+        }
+
+        Self::Internal(err)
+    }
+}
+
+impl From<BackendError> for ApiError {
+    fn from(err: BackendError) -> Self {
+        Self::Backend(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            error!(code = self.code(), "Request failed: {self}");
+        } else {
+            info!(code = self.code(), "Request rejected: {self}");
+        }
+
+        let body = Json(json!({
+            "status": "ERROR",
+            "reason": self.reason(),
+            "code": self.code(),
+        }));
+
+        match self {
+            Self::RateLimited(retry_after) => {
+                let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                (status, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+            }
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::is_unique_violation;
+    use anyhow::{anyhow, Context};
+    use diesel::result::DatabaseErrorKind;
+
+    #[test]
+    fn codes_and_statuses() {
+        let cases = [
+            (ApiError::MissingAmount, "missing_amount", 400),
+            (ApiError::AmountOutOfBounds, "amount_out_of_bounds", 400),
+            (ApiError::InvalidRequest("x".into()), "invalid_request", 400),
+            (ApiError::UserNotFound, "user_not_found", 404),
+            (ApiError::RouteNotFound, "route_not_found", 404),
+            (ApiError::NameBanned, "name_banned", 403),
+            (ApiError::NameTaken, "name_taken", 409),
+            (ApiError::PubkeyTaken, "pubkey_taken", 409),
+            (ApiError::Unauthorized, "unauthorized", 401),
+            (ApiError::TooManyStreams, "too_many_streams", 429),
+            (
+                ApiError::RateLimited(Duration::from_secs(1)),
+                "rate_limited",
+                429,
+            ),
+            (
+                ApiError::Backend(BackendError("down".into())),
+                "wallet_error",
+                502,
+            ),
+            (
+                ApiError::PriceUnavailable(anyhow!("stale")),
+                "price_unavailable",
+                503,
+            ),
+            (
+                ApiError::Database(anyhow!("gone")),
+                "database_unavailable",
+                503,
+            ),
+            (ApiError::Internal(anyhow!("oops")), "internal_error", 500),
+        ];
+        for (err, code, status) in cases {
+            assert_eq!(err.code(), code);
+            assert_eq!(err.status().as_u16(), status, "{code}");
+        }
+    }
+
+    #[test]
+    fn from_anyhow() {
+        let err = ApiError::from(anyhow::Error::new(ApiError::NameTaken));
+        assert!(matches!(err, ApiError::NameTaken));
+
+        let err: anyhow::Result<()> = Err(ApiError::UserNotFound.into());
+        let err = ApiError::from(err.context("looking up user").unwrap_err());
+        assert!(matches!(err, ApiError::UserNotFound));
+
+        let err = ApiError::from(anyhow::Error::new(BackendError("down".into())));
+        assert!(matches!(err, ApiError::Backend(_)));
+
+        let err = ApiError::from(anyhow::Error::new(diesel::result::Error::NotFound));
+        assert!(matches!(err, ApiError::Database(_)));
+
+        let err = ApiError::from(anyhow!("oops"));
+        assert!(matches!(err, ApiError::Internal(_)));
+        assert_eq!(err.reason(), "Internal server error");
+    }
+
+    #[test]
+    fn unique_violations() {
+        let violation = |kind| {
+            anyhow::Error::new(diesel::result::Error::DatabaseError(
+                kind,
+                Box::new("duplicate key".to_string()),
+            ))
+        };
+        assert!(is_unique_violation(&violation(
+            DatabaseErrorKind::UniqueViolation
+        )));
+        assert!(is_unique_violation(
+            &violation(DatabaseErrorKind::UniqueViolation).context("inserting user")
+        ));
+        assert!(!is_unique_violation(&violation(
+            DatabaseErrorKind::ForeignKeyViolation
+        )));
+        assert!(!is_unique_violation(&anyhow!("oops")));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::backend::BackendEvent;
+use crate::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Connection and sync state of the payment backend, kept up to date by [`start_wallet_monitor`].
+#[derive(Debug, Default, Clone)]
+pub struct WalletStatus {
+    pub connected: bool,
+    pub last_synced: Option<DateTime<Utc>>,
+}
+
+/// Follows the backend's event stream so health checks can report its state
+/// without making a round trip to the Spark operators.
+pub async fn start_wallet_monitor(state: State) {
+    let mut events = state.wallet.subscribe_events();
+
+    // seed from the current state after subscribing, so nothing between the two is missed
+    let current = state.wallet.connection_state();
+    {
+        let mut status = state.wallet_status.write().unwrap();
+        status.connected = current.connected;
+        if current.synced {
+            status.last_synced = Some(Utc::now());
+        }
+    }
+
+    loop {
+        match events.recv().await {
+            Ok(BackendEvent::Connected) => {
+                info!("Wallet event stream connected");
+                state.wallet_status.write().unwrap().connected = true;
+            }
+            Ok(BackendEvent::Disconnected) => {
+                warn!("Wallet event stream disconnected");
+                state.wallet_status.write().unwrap().connected = false;
+            }
+            Ok(BackendEvent::Synced) => {
+                let mut status = state.wallet_status.write().unwrap();
+                status.connected = true;
+                status.last_synced = Some(Utc::now());
+            }
+            Err(RecvError::Lagged(n)) => warn!("Wallet monitor lagged {n} events"),
+            Err(RecvError::Closed) => {
+                warn!("Wallet event stream closed");
+                state.wallet_status.write().unwrap().connected = false;
+                break;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheck {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component_id: Option<String>,
+    pub component_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_value: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_unit: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+impl HealthCheck {
+    fn new(status: HealthStatus, component_type: &'static str) -> Self {
+        Self {
+            status,
+            component_id: None,
+            component_type,
+            observed_value: None,
+            observed_unit: None,
+            output: None,
+            time: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    pub version: String,
+    pub checks: BTreeMap<&'static str, Vec<HealthCheck>>,
+}
+
+async fn check_database(state: &State) -> HealthCheck {
+    let start = Instant::now();
+    match state.storage.ping().await {
+        Ok(_) => HealthCheck {
+            observed_value: Some(start.elapsed().as_millis()),
+            observed_unit: Some("ms"),
+            ..HealthCheck::new(HealthStatus::Pass, "datastore")
+        },
+        Err(e) => {
+            warn!("Database health check failed: {e:#}");
+            HealthCheck {
+                output: Some("database unavailable".to_string()),
+                ..HealthCheck::new(HealthStatus::Fail, "datastore")
+            }
+        }
+    }
+}
+
+fn check_wallet(state: &State) -> HealthCheck {
+    let status = state.wallet_status.read().unwrap().clone();
+
+    match (status.connected, status.last_synced) {
+        (true, Some(last_synced)) => HealthCheck {
+            output: Some(format!("last synced at {last_synced}")),
+            ..HealthCheck::new(HealthStatus::Pass, "component")
+        },
+        (true, None) => HealthCheck {
+            output: Some("wallet has not finished syncing".to_string()),
+            ..HealthCheck::new(HealthStatus::Warn, "component")
+        },
+        (false, _) => HealthCheck {
+            output: Some("not connected to Spark operators".to_string()),
+            ..HealthCheck::new(HealthStatus::Fail, "component")
+        },
+    }
+}
+
+/// IETF draft RFC for HTTP API Health Checks:
+/// https://datatracker.ietf.org/doc/html/draft-inadarei-api-health-check
+///
+/// Responds with 503 if any check fails so load balancers stop routing to us.
+pub async fn health_check(Extension(state): Extension<State>) -> impl IntoResponse {
+    let mut checks = BTreeMap::new();
+    checks.insert("database:connectivity", vec![check_database(&state).await]);
+    checks.insert("spark:connectivity", vec![check_wallet(&state)]);
+
+    let status = checks
+        .values()
+        .flatten()
+        .map(|c| c.status)
+        .max()
+        .unwrap_or(HealthStatus::Pass);
+
+    let code = match status {
+        HealthStatus::Pass | HealthStatus::Warn => StatusCode::OK,
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    let resp = HealthResponse {
+        status,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        checks,
+    };
+
+    (
+        code,
+        [(header::CONTENT_TYPE, "application/health+json")],
+        Json(resp),
+    )
+}
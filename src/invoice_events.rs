@@ -0,0 +1,247 @@
+use crate::domains::resolve_domain;
+use crate::error::ApiError;
+use crate::models::invoice::{Invoice, InvoiceState};
+use crate::nip98::Nip98Auth;
+use crate::storage::Storage;
+use crate::State;
+use axum::extract::{Host, Path};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Extension;
+use futures::stream::{self, Stream};
+use lightning_invoice::Bolt11Invoice;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// How many events a listener may fall behind before it starts missing them.
+pub const CHANNEL_CAPACITY: usize = 1024;
+
+/// A state transition of an [`Invoice`], broadcast to the payment status streams.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvoiceEvent {
+    pub id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub payment_hash: Option<String>,
+    pub amount_msats: i64,
+    pub state: InvoiceState,
+}
+
+impl InvoiceEvent {
+    pub fn new(invoice: &Invoice, state: InvoiceState) -> Self {
+        Self {
+            id: invoice.id,
+            user_id: invoice.user_id,
+            payment_hash: invoice.payment_hash.clone(),
+            amount_msats: invoice.amount_msats,
+            state,
+        }
+    }
+}
+
+/// Most user invoice streams that may be open at once per user.
+pub const MAX_USER_STREAMS: usize = 4;
+
+/// Most streams that may be open at once per invoice.
+pub const MAX_INVOICE_STREAMS: usize = 4;
+
+/// Counts the open streams per user or invoice id.
+#[derive(Debug, Default)]
+pub struct OpenStreams {
+    open: Mutex<HashMap<i32, usize>>,
+}
+
+impl OpenStreams {
+    /// Counts a new stream of `id` until the returned guard is dropped, `None` if `id`
+    /// already has `max` open.
+    fn open(self: &Arc<Self>, id: i32, max: usize) -> Option<StreamGuard> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(id).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+
+        Some(StreamGuard {
+            streams: self.clone(),
+            id,
+        })
+    }
+}
+
+struct StreamGuard {
+    streams: Arc<OpenStreams>,
+    id: i32,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut open = self.streams.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.id);
+            }
+        }
+    }
+}
+
+/// Stores the payment hash of pending invoices created before invoices had one, so their
+/// streams can find them. Returns how many were updated.
+pub async fn backfill_payment_hashes(storage: &dyn Storage) -> anyhow::Result<usize> {
+    let pending = storage
+        .get_invoices_by_state(InvoiceState::Pending as i32)
+        .await?;
+
+    let mut updated = 0;
+    for invoice in pending.iter().filter(|i| i.payment_hash.is_none()) {
+        match Bolt11Invoice::from_str(&invoice.bolt11) {
+            Ok(bolt11) => {
+                let payment_hash = bolt11.payment_hash().to_string();
+                storage
+                    .set_invoice_payment_hash(invoice, &payment_hash)
+                    .await?;
+                updated += 1;
+            }
+            Err(e) => warn!("Invoice {} has an invalid bolt11: {e}", invoice.id),
+        }
+    }
+
+    Ok(updated)
+}
+
+/// Tells the streams that `invoice` is now in `new_state`.
+pub fn publish(state: &State, invoice: &Invoice, new_state: InvoiceState) {
+    // only fails when nobody is listening
+    let _ = state
+        .invoice_events
+        .send(InvoiceEvent::new(invoice, new_state));
+}
+
+fn sse_event(event: &InvoiceEvent) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event("invoice")
+        .json_data(event)
+        .expect("invoice event serializes"))
+}
+
+/// HTTP endpoint streaming the state of an invoice as Server-Sent Events, served at
+/// `/v1/invoices/{payment_hash}/events`.
+///
+/// Sends the current state right away and then every transition, the stream ends once
+/// the invoice is settled or cancelled. An invoice may have at most [`MAX_INVOICE_STREAMS`]
+/// streams open.
+pub async fn get_invoice_events(
+    Path(payment_hash): Path<String>,
+    Extension(state): Extension<State>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    // subscribe before reading the invoice so a transition in between isn't missed
+    let rx = state.invoice_events.subscribe();
+    let invoice = state
+        .storage
+        .get_invoice_by_payment_hash(&payment_hash.to_lowercase())
+        .await?
+        .ok_or(ApiError::InvoiceNotFound)?;
+    let current = InvoiceEvent::new(&invoice, InvoiceState::try_from(invoice.state)?);
+
+    let id = invoice.id;
+    let guard = state
+        .invoice_streams
+        .open(id, MAX_INVOICE_STREAMS)
+        .ok_or(ApiError::TooManyStreams)?;
+    // the guard lives as long as the stream, until it ends or the client disconnects
+    let stream = stream::unfold(Some((rx, Some(current), guard)), move |next| {
+        let state = state.clone();
+        async move {
+            let (mut rx, pending, guard) = next?;
+            let event = match pending {
+                Some(event) => event,
+                None => next_event(&state, &mut rx, id).await?,
+            };
+            let next = (!event.state.is_final()).then_some((rx, None, guard));
+            Some((sse_event(&event), next))
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Waits for the next event of invoice `id`. If events were dropped because we fell
+/// behind, the invoice is read from the database instead. `None` once the channel closes.
+async fn next_event(
+    state: &State,
+    rx: &mut broadcast::Receiver<InvoiceEvent>,
+    id: i32,
+) -> Option<InvoiceEvent> {
+    loop {
+        match rx.recv().await {
+            Ok(event) if event.id == id => return Some(event),
+            Ok(_) => {}
+            Err(RecvError::Lagged(_)) => match state.storage.get_invoice(id).await {
+                Ok(invoice) => {
+                    let invoice = invoice?;
+                    let current = InvoiceState::try_from(invoice.state).ok()?;
+                    return Some(InvoiceEvent::new(&invoice, current));
+                }
+                Err(e) => warn!("Failed to reload invoice {id}: {e}"),
+            },
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// HTTP endpoint streaming the state transitions of all of a user's invoices as
+/// Server-Sent Events, served at `/v1/users/{name}/invoices/events`.
+///
+/// Requires a NIP-98 `Authorization: Nostr` header signed by the key the user registered with.
+/// A user may have at most [`MAX_USER_STREAMS`] streams open.
+pub async fn get_user_invoice_events(
+    Nip98Auth(signer): Nip98Auth,
+    Path(name): Path<String>,
+    host: Option<Host>,
+    Extension(state): Extension<State>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let domain = resolve_domain(&state, host.as_ref().map(|h| h.0.as_str())).await?;
+    let user = state
+        .storage
+        .get_user_by_name(domain.id, &name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if user.disabled {
+        return Err(ApiError::UserDisabled);
+    }
+
+    let (user_key, _) = user.pubkey().x_only_public_key();
+    if nostr::PublicKey::from_slice(&user_key.serialize()).ok() != Some(signer) {
+        return Err(ApiError::Unauthorized);
+    }
+
+    let user_id = user.id;
+    let guard = state
+        .user_streams
+        .open(user_id, MAX_USER_STREAMS)
+        .ok_or(ApiError::TooManyStreams)?;
+    let rx = state.invoice_events.subscribe();
+    // the guard lives as long as the stream, until the client disconnects
+    let stream = stream::unfold((rx, guard), move |(mut rx, guard)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) if event.user_id == user_id => {
+                    return Some((sse_event(&event), (rx, guard)))
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Invoice event stream of user {user_id} fell behind, skipped {n} events")
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,380 @@
+use crate::backend::{BackendError, DepositStatus, PaymentStatus};
+use crate::invoice_events;
+use crate::models::deposit::{DepositState, NewDeposit};
+use crate::models::invoice::{Invoice, InvoiceState};
+use crate::models::zap::Zap;
+use crate::State;
+use anyhow::anyhow;
+use nostr::{Event, EventBuilder, JsonUtil};
+use nostr_sdk::Client;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// How often pending invoices are checked against the payment backend.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often deposit addresses are checked for on-chain payments.
+const DEPOSIT_POLL_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Max time we give the signer and relays to get a zap receipt out.
+const ZAP_RECEIPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Zap receipts being published at the same time.
+const ZAP_RECEIPT_CONCURRENCY: usize = 8;
+
+/// Attempts at publishing a zap receipt before giving up on it.
+const ZAP_RECEIPT_MAX_ATTEMPTS: u32 = 10;
+
+/// Longest wait between attempts at publishing a zap receipt.
+const ZAP_RECEIPT_MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// A zap receipt waiting to be published, see [`start_zap_receipt_publisher`].
+#[derive(Debug, Clone)]
+pub struct ZapJob {
+    pub invoice_id: i32,
+    /// Preimage reported by the backend, if the invoice doesn't have one stored
+    pub preimage: Option<String>,
+    pub attempt: u32,
+}
+
+impl ZapJob {
+    pub fn new(invoice_id: i32, preimage: Option<String>) -> Self {
+        Self {
+            invoice_id,
+            preimage,
+            attempt: 0,
+        }
+    }
+}
+
+/// Watches pending invoices and settles them once the backend reports them paid,
+/// publishing zap receipts for any that carried a zap request.
+pub async fn start_invoice_subscription(state: State) {
+    info!("Starting invoice subscription");
+    loop {
+        if let Err(e) = check_pending_invoices(&state).await {
+            error!("Error checking pending invoices: {e:?}");
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn check_pending_invoices(state: &State) -> anyhow::Result<()> {
+    let pending = state
+        .storage
+        .get_invoices_by_state(InvoiceState::Pending as i32)
+        .await?;
+
+    for invoice in pending {
+        let status = match invoice.receive_id.as_deref() {
+            None => PaymentStatus::Pending,
+            Some(id) => match state.wallet.payment_status(id).await {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Error fetching payment status for {id}: {e}");
+                    continue;
+                }
+            },
+        };
+
+        match status {
+            PaymentStatus::Paid { preimage } => {
+                let span = info_span!(
+                    "invoice",
+                    id = invoice.id,
+                    payment_hash = %invoice.bolt11().payment_hash(),
+                );
+                let res = handle_paid_invoice(state, &invoice, preimage)
+                    .instrument(span)
+                    .await;
+                if let Err(e) = res {
+                    error!("Error handling paid invoice {}: {e:?}", invoice.id);
+                }
+            }
+            PaymentStatus::Failed => {
+                state
+                    .storage
+                    .set_invoice_state(&invoice, InvoiceState::Cancelled as i32)
+                    .await?;
+                invoice_events::publish(state, &invoice, InvoiceState::Cancelled);
+            }
+            PaymentStatus::Pending if invoice.bolt11().is_expired() => {
+                state
+                    .storage
+                    .set_invoice_state(&invoice, InvoiceState::Cancelled as i32)
+                    .await?;
+                invoice_events::publish(state, &invoice, InvoiceState::Cancelled);
+            }
+            PaymentStatus::Pending => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Follows on-chain payments to users' deposit addresses from first sighting until they
+/// are claimed into the user's wallet. Stops if the backend has no deposit addresses.
+///
+/// Idles while the on-chain fallback is disabled, the setting is checked on every poll so
+/// a config reload turning it on is picked up.
+pub async fn start_deposit_tracking(state: State) {
+    info!("Starting deposit tracking");
+    loop {
+        let enabled = state
+            .settings
+            .read()
+            .unwrap()
+            .onchain_min_sendable
+            .is_some();
+        if !enabled {
+            tokio::time::sleep(DEPOSIT_POLL_INTERVAL).await;
+            continue;
+        }
+
+        match update_deposits(&state).await {
+            Ok(()) => {}
+            Err(e)
+                if e.downcast_ref::<BackendError>()
+                    .is_some_and(|e| e.is_unsupported()) =>
+            {
+                info!("Backend has no deposit addresses, not tracking deposits");
+                return;
+            }
+            Err(e) => error!("Error updating deposits: {e:?}"),
+        }
+        tokio::time::sleep(DEPOSIT_POLL_INTERVAL).await;
+    }
+}
+
+async fn update_deposits(state: &State) -> anyhow::Result<()> {
+    let users = state.storage.get_users_with_deposit_address().await?;
+
+    for user in users.iter() {
+        let Some(address) = user.deposit_address.as_deref() else {
+            continue;
+        };
+
+        for output in state.wallet.deposits(address).await? {
+            let new_state = match output.status {
+                DepositStatus::Unconfirmed => DepositState::Unconfirmed,
+                DepositStatus::Confirmed => DepositState::Confirmed,
+                DepositStatus::Claimed => DepositState::Claimed,
+                DepositStatus::Failed => DepositState::Failed,
+            } as i32;
+
+            let deposit = state
+                .storage
+                .get_deposit_by_outpoint(&output.txid, output.vout as i32)
+                .await?;
+            match deposit {
+                None => {
+                    let new_deposit = NewDeposit {
+                        user_id: user.id,
+                        address: address.to_string(),
+                        txid: output.txid,
+                        vout: output.vout as i32,
+                        amount_sats: output.amount_sats as i64,
+                        state: new_state,
+                    };
+                    state.storage.insert_deposit(new_deposit).await?;
+                }
+                // claimed and failed deposits are final
+                Some(d) if d.state == DepositState::Claimed as i32 => continue,
+                Some(d) if d.state == DepositState::Failed as i32 => continue,
+                Some(d) if d.state == new_state => continue,
+                Some(d) => state.storage.set_deposit_state(&d, new_state).await?,
+            }
+
+            if new_state == DepositState::Claimed as i32 {
+                info!(
+                    "Deposit of {} sats to {} claimed",
+                    output.amount_sats, user.name
+                );
+                state.metrics.deposits_claimed.inc();
+                state.metrics.deposited_sats.inc_by(output.amount_sats);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_paid_invoice(
+    state: &State,
+    invoice: &Invoice,
+    preimage: Option<String>,
+) -> anyhow::Result<()> {
+    state
+        .storage
+        .set_invoice_state(invoice, InvoiceState::Settled as i32)
+        .await?;
+    invoice_events::publish(state, invoice, InvoiceState::Settled);
+    let zap = state.storage.get_zap(invoice.id).await?;
+
+    state.metrics.invoices_settled.inc();
+    state
+        .metrics
+        .settled_msats
+        .inc_by(invoice.amount_msats as u64);
+
+    if zap.is_some_and(|z| z.event_id.is_none()) {
+        let _ = state.zap_receipts.send(ZapJob::new(invoice.id, preimage));
+    }
+
+    Ok(())
+}
+
+/// Publishes zap receipts as invoices with zap requests settle, up to
+/// [`ZAP_RECEIPT_CONCURRENCY`] at a time so a slow signer or relay doesn't hold up the rest.
+///
+/// Receipts that fail, e.g. because the remote signer isn't responding, are queued again
+/// with a backoff. Settled zaps left unpublished by an earlier run are queued on start.
+pub async fn start_zap_receipt_publisher(state: State, mut jobs: mpsc::UnboundedReceiver<ZapJob>) {
+    info!("Starting zap receipt publisher");
+    if let Err(e) = queue_unpublished_zaps(&state).await {
+        error!("Error queueing unpublished zap receipts: {e:?}");
+    }
+
+    let permits = Arc::new(Semaphore::new(ZAP_RECEIPT_CONCURRENCY));
+    let in_flight = Arc::new(Mutex::new(HashSet::new()));
+    while let Some(job) = jobs.recv().await {
+        // the same zap can be queued twice, e.g. on start and when its invoice settles
+        if !in_flight.lock().unwrap().insert(job.invoice_id) {
+            continue;
+        }
+
+        let permit = permits.clone().acquire_owned().await.expect("never closed");
+        let state = state.clone();
+        let in_flight = in_flight.clone();
+        tokio::spawn(async move {
+            let invoice_id = job.invoice_id;
+            run_job(&state, job).await;
+            in_flight.lock().unwrap().remove(&invoice_id);
+            drop(permit);
+        });
+    }
+}
+
+/// Publishes the job's zap receipt, queueing it again with a backoff if that fails.
+async fn run_job(state: &State, job: ZapJob) {
+    let res = tokio::time::timeout(ZAP_RECEIPT_TIMEOUT, publish_job(state, &job)).await;
+    let err = match res {
+        Ok(Ok(())) => return,
+        Ok(Err(e)) => format!("{e:?}"),
+        Err(_) => "timed out".to_string(),
+    };
+
+    let attempt = job.attempt + 1;
+    if attempt >= ZAP_RECEIPT_MAX_ATTEMPTS {
+        error!(
+            "Giving up on zap receipt for {} after {attempt} attempts: {err}",
+            job.invoice_id
+        );
+        return;
+    }
+
+    let backoff = (Duration::from_secs(5) * 2u32.pow(attempt)).min(ZAP_RECEIPT_MAX_BACKOFF);
+    warn!(
+        "Error publishing zap receipt for {}, retrying in {backoff:?}: {err}",
+        job.invoice_id
+    );
+    let queue = state.zap_receipts.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(backoff).await;
+        let _ = queue.send(ZapJob { attempt, ..job });
+    });
+}
+
+async fn queue_unpublished_zaps(state: &State) -> anyhow::Result<()> {
+    for zap in state.storage.get_zaps().await? {
+        if zap.event_id.is_some() {
+            continue;
+        }
+        let settled = state
+            .storage
+            .get_invoice(zap.id)
+            .await?
+            .is_some_and(|i| i.state == InvoiceState::Settled as i32);
+        if settled {
+            let _ = state.zap_receipts.send(ZapJob::new(zap.id, None));
+        }
+    }
+
+    Ok(())
+}
+
+async fn publish_job(state: &State, job: &ZapJob) -> anyhow::Result<()> {
+    let invoice = state
+        .storage
+        .get_invoice(job.invoice_id)
+        .await?
+        .ok_or(anyhow!("Invoice not found"))?;
+    let zap = match state.storage.get_zap(job.invoice_id).await? {
+        Some(zap) if zap.event_id.is_none() => zap,
+        // published in the meantime, e.g. with `zap resend`
+        _ => return Ok(()),
+    };
+
+    let preimage = Some(invoice.preimage.clone())
+        .filter(|p| !p.is_empty())
+        .or(job.preimage.clone());
+    publish_zap_receipt(state, &invoice, &zap, preimage).await?;
+    state.metrics.zap_receipts_published.inc();
+
+    Ok(())
+}
+
+pub(crate) async fn publish_zap_receipt(
+    state: &State,
+    invoice: &Invoice,
+    zap: &Zap,
+    preimage: Option<String>,
+) -> anyhow::Result<()> {
+    let zap_request = Event::from_json(&zap.request)?;
+
+    let relays: Vec<String> = zap_request
+        .tags
+        .iter()
+        .find_map(|t| match t.as_slice() {
+            [k, urls @ ..] if k == "relays" => Some(urls.to_vec()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    if relays.is_empty() {
+        warn!("Zap request for invoice {} has no relays", invoice.id);
+        return Ok(());
+    }
+
+    let event = state
+        .signer
+        .sign(EventBuilder::zap_receipt(
+            invoice.bolt11.clone(),
+            preimage,
+            &zap_request,
+        ))
+        .await?;
+
+    info!("Broadcasting zap receipt: {}", event.as_json());
+
+    let client = Client::default();
+    for relay in relays {
+        if let Err(e) = client.add_relay(&relay).await {
+            warn!("Skipping invalid zap relay {relay}: {e}");
+        }
+    }
+    client.connect().await;
+    let res = client.send_event(&event).await;
+    let _ = client.disconnect().await;
+    res?;
+
+    state
+        .storage
+        .set_zap_event_id(zap, event.id.to_hex())
+        .await?;
+
+    Ok(())
+}
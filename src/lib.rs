@@ -0,0 +1,163 @@
+use axum::extract::DefaultBodyLimit;
+use axum::http::{HeaderName, Method};
+use axum::middleware::from_fn;
+use axum::routing::{get, post};
+use axum::{http, Extension, Router};
+use std::sync::{Arc, OnceLock, RwLock};
+use tokio::sync::{broadcast, mpsc};
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::Level;
+
+use crate::admin::admin_router;
+use crate::backend::InvoiceBackend;
+use crate::config::{PublicUrl, Settings};
+use crate::health::{health_check, WalletStatus};
+use crate::invoice_events::{
+    get_invoice_events, get_user_invoice_events, InvoiceEvent, OpenStreams,
+};
+use crate::invoice_subscriber::ZapJob;
+use crate::metrics::{metrics_route, Metrics};
+use crate::price::{Currency, PriceSource};
+use crate::qr::get_qr;
+use crate::rate_limit::{limit_by_ip, limit_by_name, limit_registrations, RateLimits};
+use crate::routes::*;
+use crate::signer::ZapSigner;
+use crate::storage::Storage;
+
+pub mod admin;
+pub mod backend;
+pub mod bip353;
+pub mod cli;
+pub mod config;
+pub mod domains;
+pub mod error;
+pub mod health;
+pub mod invoice_events;
+pub mod invoice_subscriber;
+pub mod logging;
+pub mod metrics;
+pub mod models;
+pub mod nip98;
+pub mod price;
+pub mod qr;
+pub mod rate_limit;
+pub mod routes;
+pub mod seed;
+pub mod signer;
+pub mod storage;
+
+#[derive(Clone)]
+pub struct State {
+    pub storage: Arc<dyn Storage>,
+    /// Signs zap receipts
+    pub signer: ZapSigner,
+    /// The signer's pubkey, advertised as `nostrPubkey`. Unset until a remote signer answers
+    pub nostr_pubkey: Arc<OnceLock<nostr::PublicKey>>,
+    /// Queue of zap receipts to publish
+    pub zap_receipts: mpsc::UnboundedSender<ZapJob>,
+    /// Invoice state transitions, feeding the payment status streams
+    pub invoice_events: broadcast::Sender<InvoiceEvent>,
+    /// Open user invoice streams, to limit them per user
+    pub user_streams: Arc<OpenStreams>,
+    /// Open invoice streams, to limit them per invoice
+    pub invoice_streams: Arc<OpenStreams>,
+    pub wallet: Arc<dyn InvoiceBackend>,
+    pub metrics: Arc<Metrics>,
+    pub wallet_status: Arc<RwLock<WalletStatus>>,
+    pub rate_limits: Arc<RateLimits>,
+    pub prices: Arc<dyn PriceSource>,
+
+    // -- config options --
+    pub domain: String,
+    pub public_url: PublicUrl,
+    /// Settings that are reloaded on SIGHUP
+    pub settings: Arc<RwLock<Settings>>,
+    pub currencies: Vec<Currency>,
+    pub admin_token: Option<String>,
+    pub admin_pubkeys: Vec<nostr::PublicKey>,
+    pub trust_proxy: bool,
+}
+
+/// The HTTP API, with `state` available to every handler as an extension.
+pub fn router(state: State) -> Router {
+    Router::new()
+        .route("/health-check", get(health_check))
+        .route("/metrics", get(metrics_route))
+        .route(
+            "/get-invoice/:name",
+            get(get_invoice)
+                .layer(from_fn(limit_by_name))
+                .layer(from_fn(limit_by_ip)),
+        )
+        .route(
+            "/get-invoice/:name/:link",
+            get(get_invoice)
+                .layer(from_fn(limit_by_name))
+                .layer(from_fn(limit_by_ip)),
+        )
+        .route("/verify/:desc_hash/:pay_hash", get(verify))
+        .route(
+            "/.well-known/lnurlp/:name",
+            get(get_lnurl_pay)
+                .layer(from_fn(limit_by_name))
+                .layer(from_fn(limit_by_ip)),
+        )
+        .route(
+            "/.well-known/lnurlp/:name/:link",
+            get(get_lnurl_pay)
+                .layer(from_fn(limit_by_name))
+                .layer(from_fn(limit_by_ip)),
+        )
+        .route(
+            "/v1/users/:name/lnurl",
+            get(get_lnurl).layer(from_fn(limit_by_ip)),
+        )
+        .route(
+            "/v1/users/:name/invoices/events",
+            get(get_user_invoice_events).layer(from_fn(limit_by_ip)),
+        )
+        .route(
+            "/v1/invoices/:payment_hash/events",
+            get(get_invoice_events).layer(from_fn(limit_by_ip)),
+        )
+        .route("/qr/:file", get(get_qr).layer(from_fn(limit_by_ip)))
+        .route(
+            "/v1/register",
+            post(register_route).layer(from_fn(limit_registrations)),
+        )
+        .nest("/admin", admin_router())
+        .fallback(fallback)
+        .layer(Extension(state))
+        .layer(PropagateRequestIdLayer::new(HeaderName::from_static(
+            logging::REQUEST_ID_HEADER,
+        )))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(logging::request_span)
+                .on_response(
+                    DefaultOnResponse::new()
+                        .level(Level::INFO)
+                        .latency_unit(LatencyUnit::Millis),
+                ),
+        )
+        .layer(SetRequestIdLayer::new(
+            HeaderName::from_static(logging::REQUEST_ID_HEADER),
+            MakeRequestUuid,
+        ))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION])
+                .allow_methods([
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::DELETE,
+                    Method::OPTIONS,
+                ]),
+        )
+        .layer(DefaultBodyLimit::max(1_000_000)) // max 1mb body size
+}
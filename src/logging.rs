@@ -0,0 +1,50 @@
+use crate::config::LogFormat;
+use anyhow::anyhow;
+use axum::http::Request;
+use tracing::field::{display, Empty};
+use tracing::{info_span, Span};
+use tracing_subscriber::EnvFilter;
+
+/// Header carrying the id of each request, set by us when the client didn't.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Installs the global subscriber. Verbosity is set with `RUST_LOG`, defaulting to info.
+///
+/// Records from crates still using `log`, like the Spark SDK, go through the same pipeline.
+pub fn init(format: LogFormat) -> anyhow::Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Pretty => builder.try_init(),
+        LogFormat::Json => builder.json().with_current_span(true).try_init(),
+    }
+    .map_err(|e| anyhow!("Failed to set up logging: {e}"))
+}
+
+/// Span wrapping a whole request. Handlers fill in `user` and `payment_hash`
+/// through [`record_user`] and [`record_payment_hash`] once they know them.
+pub fn request_span<B>(req: &Request<B>) -> Span {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    info_span!(
+        "request",
+        request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        user = Empty,
+        payment_hash = Empty,
+    )
+}
+
+pub fn record_user(name: &str) {
+    Span::current().record("user", name);
+}
+
+pub fn record_payment_hash(payment_hash: impl std::fmt::Display) {
+    Span::current().record("payment_hash", display(payment_hash));
+}
@@ -1,95 +1,205 @@
-use axum::extract::DefaultBodyLimit;
-use axum::http::Method;
-use axum::routing::{get, post};
-use axum::{http, Extension, Router};
-use clap::Parser;
-use diesel::r2d2::{ConnectionManager, Pool};
-use diesel::PgConnection;
+use lnurl_spark::backend::mock::MockBackend;
+use lnurl_spark::backend::spark::SparkBackend;
+use lnurl_spark::backend::InvoiceBackend;
+use lnurl_spark::config::*;
+use lnurl_spark::health::{start_wallet_monitor, WalletStatus};
+use lnurl_spark::invoice_subscriber::{
+    start_deposit_tracking, start_invoice_subscription, start_zap_receipt_publisher,
+};
+use lnurl_spark::metrics::Metrics;
+use lnurl_spark::price::{Currency, FixedRateSource, HttpPriceSource, PriceSource};
+use lnurl_spark::rate_limit::RateLimits;
+use lnurl_spark::signer::{client_keys, start_public_key_fetch, ZapSigner};
+use lnurl_spark::storage::{DbPool, DieselStorage, Storage};
+use lnurl_spark::{bip353, cli, invoice_events, logging, router, seed, State};
 use nostr::Keys;
-use spark::signer::DefaultSigner;
-use spark_wallet::SparkWallet;
 use std::str::FromStr;
-use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
-
-use crate::config::*;
-use crate::routes::*;
-
-mod config;
-mod models;
-mod routes;
-
-#[derive(Clone)]
-pub struct State {
-    pub db_pool: Pool<ConnectionManager<PgConnection>>,
-    pub keys: Keys,
-    pub wallet: Arc<SparkWallet<DefaultSigner>>,
-
-    // -- config options --
-    pub domain: String,
-    pub min_sendable: u64,
-    pub max_sendable: u64,
-}
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
-    pretty_env_logger::try_init()?;
-    let config: Config = Config::parse();
+    let config = Config::load()?;
+    logging::init(config.log_format)?;
+    let public_url = config.public_url()?;
+
+    let db_pool = match config.database {
+        Database::Postgres => {
+            let pg_url = config
+                .pg_url
+                .as_deref()
+                .ok_or(anyhow::anyhow!("--pg-url is required for postgres"))?;
+            DbPool::postgres(pg_url, config.db_pool_size)?
+        }
+        Database::Sqlite => DbPool::sqlite(&config.sqlite_path, config.db_pool_size)?,
+    };
+
+    if config.command == Some(Command::Migrate) || config.run_migrations {
+        let applied = db_pool.run_migrations()?;
+        if applied.is_empty() {
+            info!("Database is up to date");
+        } else {
+            info!("Applied migrations: {}", applied.join(", "));
+        }
+
+        if config.command == Some(Command::Migrate) {
+            return Ok(());
+        }
+    }
+    db_pool.check_schema_version()?;
+
+    let metrics = Arc::new(Metrics::new()?);
+    let storage: Arc<dyn Storage> = Arc::new(DieselStorage::new(
+        db_pool,
+        metrics.db_pool_wait_seconds.clone(),
+    ));
+
+    // commands that only need the database run before connecting to the wallet
+    match config.command.as_ref() {
+        Some(Command::Bip353Zone { domain, ttl }) => {
+            let zone = bip353::zone_fragment(
+                storage.as_ref(),
+                &public_url,
+                &config.domain,
+                domain.as_deref(),
+                *ttl,
+            )
+            .await?;
+            print!("{zone}");
+            return Ok(());
+        }
+        Some(Command::User(cmd)) => {
+            return cli::run_user_command(storage.as_ref(), &config.domain, cmd).await;
+        }
+        Some(Command::Invoice(cmd)) => {
+            return cli::run_invoice_command(storage.as_ref(), cmd).await;
+        }
+        _ => {}
+    }
 
-    let keys = Keys::from_str(&config.nsec)?;
+    let backfilled = invoice_events::backfill_payment_hashes(storage.as_ref()).await?;
+    if backfilled > 0 {
+        info!("Stored the payment hash of {backfilled} pending invoices");
+    }
 
-    let manager = ConnectionManager::<PgConnection>::new(config.pg_url.clone());
-    let db_pool = Pool::builder()
-        .max_size(10) // should be a multiple of 100, our database connection limit
-        .test_on_check_out(true)
-        .build(manager)
-        .expect("Unable to build DB connection pool");
+    let keys = config.nsec.as_deref().map(Keys::from_str).transpose()?;
 
-    let spark_config = config.spark_config();
-    let signer = DefaultSigner::new(keys.secret_key().as_secret_bytes(), spark_config.network)?;
-    let wallet = Arc::new(SparkWallet::connect(spark_config, signer).await?);
+    let admin_pubkeys = config
+        .admin_pubkeys
+        .iter()
+        .map(|pk| nostr::PublicKey::parse(pk))
+        .collect::<Result<Vec<_>, _>>()?;
 
+    if let Some(Command::Wallet(WalletCommand::ExportLegacySeed { out })) = config.command.as_ref()
+    {
+        let keys = keys.as_ref().ok_or(anyhow::anyhow!("--nsec is required"))?;
+        seed::export_legacy_seed(keys, out, config.seed_password.as_deref())?;
+        println!("Wrote wallet seed to {}", out.display());
+        return Ok(());
+    }
+
+    let wallet: Arc<dyn InvoiceBackend> = match config.backend {
+        Backend::Spark => Arc::new(
+            SparkBackend::connect(config.spark_config()?, &config.wallet_seed(keys.as_ref())?)
+                .await?,
+        ),
+        Backend::Mock => Arc::new(MockBackend::new(
+            config.network,
+            Duration::from_secs(config.mock_settle_secs),
+        )),
+    };
+
+    if let Some(Command::Wallet(cmd)) = config.command.as_ref() {
+        return cli::run_wallet_command(wallet.as_ref(), cmd).await;
+    }
+
+    let signer = match (config.nostr_bunker.as_deref(), keys.as_ref()) {
+        (Some(bunker), _) => {
+            let client_keys = client_keys(&config.nostr_bunker_key_file)?;
+            ZapSigner::remote(
+                bunker,
+                client_keys,
+                Duration::from_secs(config.signer_timeout_secs),
+            )?
+        }
+        (None, Some(keys)) => ZapSigner::Keys(keys.clone()),
+        (None, None) => anyhow::bail!("--nsec or --nostr-bunker is required"),
+    };
+    let nostr_pubkey = Arc::new(OnceLock::new());
+    match &signer {
+        ZapSigner::Keys(keys) => {
+            let _ = nostr_pubkey.set(keys.public_key());
+        }
+        ZapSigner::Remote(_) => {
+            tokio::spawn(start_public_key_fetch(signer.clone(), nostr_pubkey.clone()));
+        }
+    }
+
+    let currencies = config
+        .currencies
+        .iter()
+        .map(|c| Currency::from_code(c))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let prices: Arc<dyn PriceSource> = match config.price_source {
+        PriceSourceKind::Http => {
+            let source = Arc::new(HttpPriceSource::new(config.price_url.clone()));
+            tokio::spawn(source.clone().start_refresh());
+            source
+        }
+        PriceSourceKind::Fixed => Arc::new(FixedRateSource::parse(&config.fixed_rates)?),
+    };
+
+    let (zap_receipts, zap_jobs) = mpsc::unbounded_channel();
+    let (invoice_events, _) = broadcast::channel(invoice_events::CHANNEL_CAPACITY);
+    let settings = config.settings();
     let state = State {
-        db_pool: db_pool.clone(),
-        keys: keys.clone(),
+        storage,
+        signer,
+        nostr_pubkey,
+        zap_receipts,
+        invoice_events,
+        user_streams: Default::default(),
+        invoice_streams: Default::default(),
         wallet,
+        metrics,
+        wallet_status: Arc::new(RwLock::new(WalletStatus::default())),
+        prices,
+        rate_limits: Arc::new(RateLimits::new(
+            config.rate_limit_ip,
+            config.rate_limit_name,
+            config.rate_limit_register,
+        )),
         domain: config.domain,
-        min_sendable: config.min_sendable,
-        max_sendable: config.max_sendable,
+        public_url,
+        settings: Arc::new(RwLock::new(settings)),
+        currencies,
+        admin_token: config.admin_token,
+        admin_pubkeys,
+        trust_proxy: config.trust_proxy,
     };
 
+    if let Some(Command::Zap(cmd)) = config.command.as_ref() {
+        return cli::run_zap_command(&state, cmd).await;
+    }
+
     let addr: std::net::SocketAddr = format!("{}:{}", config.bind, config.port)
         .parse()
         .expect("Failed to parse bind/port for webserver");
 
-    println!("Webserver running on http://{addr}");
-
-    let server_router = Router::new()
-        .route("/health-check", get(health_check))
-        .route("/get-invoice/:hash", get(get_invoice))
-        .route("/verify/:desc_hash/:pay_hash", get(verify))
-        .route("/.well-known/lnurlp/:name", get(get_lnurl_pay))
-        .route("/v1/register", post(register_route))
-        .fallback(fallback)
-        .layer(Extension(state.clone()))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_headers([http::header::CONTENT_TYPE, http::header::AUTHORIZATION])
-                .allow_methods([
-                    Method::GET,
-                    Method::POST,
-                    Method::PUT,
-                    Method::DELETE,
-                    Method::OPTIONS,
-                ]),
-        )
-        .layer(DefaultBodyLimit::max(1_000_000)); // max 1mb body size
-
-    let server = axum::Server::bind(&addr).serve(server_router.into_make_service());
-
-    // todo Invoice event stream for zaps
+    info!("Webserver running on http://{addr}");
+
+    let app = router(state.clone());
+    let server = axum::Server::bind(&addr)
+        .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>());
+
+    tokio::spawn(start_wallet_monitor(state.clone()));
+    tokio::spawn(start_invoice_subscription(state.clone()));
+    tokio::spawn(start_zap_receipt_publisher(state.clone(), zap_jobs));
+    tokio::spawn(start_deposit_tracking(state.clone()));
+    tokio::spawn(start_config_reload(state.clone()));
 
     let graceful = server.with_graceful_shutdown(async {
         tokio::signal::ctrl_c()
@@ -99,7 +209,7 @@ async fn main() -> anyhow::Result<()> {
 
     // Await the server to receive the shutdown signal
     if let Err(e) = graceful.await {
-        eprintln!("shutdown error: {e}");
+        error!("shutdown error: {e}");
     }
 
     Ok(())
@@ -0,0 +1,137 @@
+use crate::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::Extension;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+/// Prometheus metrics for the server, exposed at `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub lnurl_lookups: IntCounter,
+    pub invoices_created: IntCounter,
+    pub invoices_reused: IntCounter,
+    pub invoice_failures: IntCounterVec,
+    pub invoices_settled: IntCounter,
+    pub settled_msats: IntCounter,
+    pub deposits_claimed: IntCounter,
+    pub deposited_sats: IntCounter,
+    pub zap_receipts_published: IntCounter,
+    pub registrations: IntCounter,
+    pub create_invoice_seconds: Histogram,
+    pub db_pool_wait_seconds: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new_custom(Some("lnurl".to_string()), None)?;
+
+        let lnurl_lookups = IntCounter::new(
+            "lookups_total",
+            "Number of LNURL-pay lookups served from .well-known/lnurlp",
+        )?;
+        let invoices_created = IntCounter::new(
+            "invoices_created_total",
+            "Number of invoices successfully created for LNURL callbacks",
+        )?;
+        let invoices_reused = IntCounter::new(
+            "invoices_reused_total",
+            "Number of repeated LNURL callbacks answered with an existing pending invoice",
+        )?;
+        let invoice_failures = IntCounterVec::new(
+            Opts::new(
+                "invoice_failures_total",
+                "Number of LNURL callbacks that failed to create an invoice",
+            ),
+            &["reason"],
+        )?;
+        let invoices_settled = IntCounter::new(
+            "invoices_settled_total",
+            "Number of invoices that have been paid",
+        )?;
+        let settled_msats = IntCounter::new(
+            "settled_msats_total",
+            "Total amount of settled invoices in millisatoshis",
+        )?;
+        let deposits_claimed = IntCounter::new(
+            "deposits_claimed_total",
+            "Number of on-chain deposits claimed into users' wallets",
+        )?;
+        let deposited_sats = IntCounter::new(
+            "deposited_sats_total",
+            "Total amount of claimed on-chain deposits in satoshis",
+        )?;
+        let zap_receipts_published = IntCounter::new(
+            "zap_receipts_published_total",
+            "Number of zap receipts published to nostr relays",
+        )?;
+        let registrations = IntCounter::new(
+            "registrations_total",
+            "Number of lightning addresses registered",
+        )?;
+        let create_invoice_seconds = Histogram::with_opts(HistogramOpts::new(
+            "create_invoice_seconds",
+            "Latency of invoice creation calls to the payment backend",
+        ))?;
+        let db_pool_wait_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "db_pool_wait_seconds",
+                "Time spent waiting for a database connection from the pool",
+            )
+            .buckets(vec![0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0]),
+        )?;
+
+        registry.register(Box::new(lnurl_lookups.clone()))?;
+        registry.register(Box::new(invoices_created.clone()))?;
+        registry.register(Box::new(invoices_reused.clone()))?;
+        registry.register(Box::new(invoice_failures.clone()))?;
+        registry.register(Box::new(invoices_settled.clone()))?;
+        registry.register(Box::new(settled_msats.clone()))?;
+        registry.register(Box::new(deposits_claimed.clone()))?;
+        registry.register(Box::new(deposited_sats.clone()))?;
+        registry.register(Box::new(zap_receipts_published.clone()))?;
+        registry.register(Box::new(registrations.clone()))?;
+        registry.register(Box::new(create_invoice_seconds.clone()))?;
+        registry.register(Box::new(db_pool_wait_seconds.clone()))?;
+
+        Ok(Self {
+            registry,
+            lnurl_lookups,
+            invoices_created,
+            invoices_reused,
+            invoice_failures,
+            invoices_settled,
+            settled_msats,
+            deposits_claimed,
+            deposited_sats,
+            zap_receipts_published,
+            registrations,
+            create_invoice_seconds,
+            db_pool_wait_seconds,
+        })
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> anyhow::Result<String> {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+/// HTTP endpoint for Prometheus to scrape.
+pub async fn metrics_route(Extension(state): Extension<State>) -> impl IntoResponse {
+    match state.metrics.encode() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            [(header::CONTENT_TYPE, "text/plain")],
+            format!("Failed to encode metrics: {e}"),
+        ),
+    }
+}
@@ -0,0 +1,52 @@
+use crate::models::schema::banned_names;
+use crate::models::{db_run, lower, DbConnection};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(QueryableByName, Queryable, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+#[diesel(table_name = banned_names)]
+pub struct BannedName {
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl BannedName {
+    pub fn get_banned_names(conn: &mut DbConnection) -> anyhow::Result<Vec<BannedName>> {
+        db_run!(conn, |conn| Ok(banned_names::table.load::<Self>(conn)?))
+    }
+
+    /// Whether `name` is banned, ignoring case.
+    pub fn is_banned(conn: &mut DbConnection, name: &str) -> anyhow::Result<bool> {
+        db_run!(conn, |conn| Ok(banned_names::table
+            .filter(lower(banned_names::name).eq(name.to_lowercase()))
+            .count()
+            .get_result::<i64>(conn)?
+            > 0))
+    }
+
+    /// Bans `name`, stored lowercased.
+    pub fn ban(conn: &mut DbConnection, name: &str) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::insert_into(banned_names::table)
+                .values(banned_names::name.eq(name.to_lowercase()))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        });
+
+        Ok(())
+    }
+
+    /// Lifts every ban of `name`, ignoring case.
+    pub fn unban(conn: &mut DbConnection, name: &str) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::delete(
+                banned_names::table.filter(lower(banned_names::name).eq(name.to_lowercase())),
+            )
+            .execute(conn)?;
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use crate::models::schema::deposits;
+use crate::models::{db_run, DbConnection};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// An on-chain payment to a user's deposit address, tracked per output until
+/// it is claimed into their wallet.
+#[derive(
+    QueryableByName, Queryable, AsChangeset, Serialize, Deserialize, Debug, Clone, PartialEq,
+)]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+#[diesel(table_name = deposits)]
+pub struct Deposit {
+    pub id: i32,
+    pub user_id: i32,
+    pub address: String,
+    pub txid: String,
+    pub vout: i32,
+    pub amount_sats: i64,
+    /// See [`DepositState`]
+    pub state: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl Deposit {
+    pub fn get_deposits(conn: &mut DbConnection) -> anyhow::Result<Vec<Deposit>> {
+        db_run!(conn, |conn| Ok(deposits::table.load::<Self>(conn)?))
+    }
+
+    pub fn get_by_outpoint(
+        conn: &mut DbConnection,
+        txid: &str,
+        vout: i32,
+    ) -> anyhow::Result<Option<Deposit>> {
+        db_run!(conn, |conn| Ok(deposits::table
+            .filter(deposits::txid.eq(txid))
+            .filter(deposits::vout.eq(vout))
+            .first::<Deposit>(conn)
+            .optional()?))
+    }
+
+    pub fn set_state(&self, conn: &mut DbConnection, s: i32) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(deposits::table)
+                .filter(deposits::id.eq(self.id))
+                .set(deposits::state.eq(s))
+                .execute(conn)?;
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = deposits)]
+pub struct NewDeposit {
+    pub user_id: i32,
+    pub address: String,
+    pub txid: String,
+    pub vout: i32,
+    pub amount_sats: i64,
+    pub state: i32,
+}
+
+impl NewDeposit {
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<Deposit> {
+        db_run!(conn, |conn| diesel::insert_into(deposits::table)
+            .values(self)
+            .get_result::<Deposit>(conn)
+            .map_err(|e| e.into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum DepositState {
+    /// Seen in the mempool or in a block without enough confirmations.
+    Unconfirmed = 0,
+    /// Confirmed on-chain, waiting to be claimed into the user's wallet.
+    Confirmed = 1,
+    /// Claimed, the user has been credited.
+    Claimed = 2,
+    /// The claim failed, e.g. the output was spent elsewhere.
+    Failed = 3,
+}
@@ -0,0 +1,60 @@
+use crate::models::schema::domains;
+use crate::models::{db_run, DbConnection};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// An additional domain lightning addresses can be registered under.
+///
+/// The server's primary domain is configured with `--domain` and isn't stored here,
+/// users of the primary domain have no `domain_id`.
+#[derive(
+    QueryableByName, Queryable, AsChangeset, Serialize, Deserialize, Debug, Clone, PartialEq,
+)]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+#[diesel(table_name = domains)]
+pub struct Domain {
+    pub id: i32,
+    pub domain: String,
+    pub min_sendable: i64,
+    pub max_sendable: i64,
+    pub comment_allowed: i32,
+}
+
+impl Domain {
+    pub fn get_domains(conn: &mut DbConnection) -> anyhow::Result<Vec<Domain>> {
+        db_run!(conn, |conn| Ok(domains::table.load::<Self>(conn)?))
+    }
+
+    pub fn get_by_domain(conn: &mut DbConnection, domain: &str) -> anyhow::Result<Option<Domain>> {
+        db_run!(conn, |conn| Ok(domains::table
+            .filter(domains::domain.eq(domain))
+            .first::<Domain>(conn)
+            .optional()?))
+    }
+
+    pub fn delete(&self, conn: &mut DbConnection) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::delete(domains::table.filter(domains::id.eq(self.id))).execute(conn)?;
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Insertable, Deserialize, Debug, Clone)]
+#[diesel(table_name = domains)]
+pub struct NewDomain {
+    pub domain: String,
+    pub min_sendable: i64,
+    pub max_sendable: i64,
+    pub comment_allowed: i32,
+}
+
+impl NewDomain {
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<Domain> {
+        db_run!(conn, |conn| diesel::insert_into(domains::table)
+            .values(self)
+            .get_result::<Domain>(conn)
+            .map_err(|e| e.into()))
+    }
+}
@@ -1,5 +1,9 @@
-use crate::models::schema::invoice;
+use crate::models::schema::{invoice, zaps};
+use crate::models::zap::Zap;
+use crate::models::{db_run, DbConnection};
+use diesel::dsl::{count_star, sql};
 use diesel::prelude::*;
+use diesel::sql_types::BigInt;
 use lightning_invoice::Bolt11Invoice;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
@@ -7,7 +11,7 @@ use std::str::FromStr;
 #[derive(
     QueryableByName, Queryable, AsChangeset, Serialize, Deserialize, Debug, Clone, PartialEq,
 )]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 #[diesel(table_name = invoice)]
 pub struct Invoice {
     pub id: i32,
@@ -17,6 +21,17 @@ pub struct Invoice {
     pub preimage: String,
     pub lnurlp_comment: Option<String>,
     pub state: i32,
+    pub receive_id: Option<String>,
+    pub pay_link_id: Option<i32>,
+    pub fiat_currency: Option<String>,
+    /// Fiat amount in the currency's smallest unit, when paid in or priced in fiat
+    pub fiat_amount: Option<i64>,
+    /// Price of one bitcoin in `fiat_currency` used for the conversion
+    pub fiat_rate: Option<f64>,
+    /// Hash of the callback that created the invoice, see [`Invoice::get_pending_by_dedupe_key`]
+    pub dedupe_key: Option<String>,
+    /// Hex payment hash of `bolt11`
+    pub payment_hash: Option<String>,
 }
 
 impl Invoice {
@@ -24,28 +39,95 @@ impl Invoice {
         Bolt11Invoice::from_str(&self.bolt11).expect("invalid bolt11")
     }
 
-    pub fn get_invoices(conn: &mut PgConnection) -> anyhow::Result<Vec<Invoice>> {
-        Ok(invoice::table.load::<Self>(conn)?)
+    pub fn get_invoices(conn: &mut DbConnection) -> anyhow::Result<Vec<Invoice>> {
+        db_run!(conn, |conn| Ok(invoice::table.load::<Self>(conn)?))
     }
 
-    pub fn get_by_id(conn: &mut PgConnection, user_id: i32) -> anyhow::Result<Option<Invoice>> {
-        Ok(invoice::table
-            .filter(invoice::id.eq(user_id))
+    pub fn get_by_id(conn: &mut DbConnection, id: i32) -> anyhow::Result<Option<Invoice>> {
+        db_run!(conn, |conn| Ok(invoice::table
+            .filter(invoice::id.eq(id))
             .first::<Invoice>(conn)
-            .optional()?)
+            .optional()?))
     }
 
-    pub fn get_by_state(conn: &mut PgConnection, state: i32) -> anyhow::Result<Vec<Invoice>> {
-        Ok(invoice::table
+    /// Number of invoices in each state along with their total amount in msats.
+    pub fn totals_by_state(conn: &mut DbConnection) -> anyhow::Result<Vec<(i32, i64, i64)>> {
+        db_run!(conn, |conn| Ok(invoice::table
+            .group_by(invoice::state)
+            .select((
+                invoice::state,
+                count_star(),
+                // SUM of a BIGINT is NUMERIC on Postgres
+                sql::<BigInt>("CAST(COALESCE(SUM(amount_msats), 0) AS BIGINT)"),
+            ))
+            .load::<(i32, i64, i64)>(conn)?))
+    }
+
+    pub fn get_by_state(conn: &mut DbConnection, state: i32) -> anyhow::Result<Vec<Invoice>> {
+        db_run!(conn, |conn| Ok(invoice::table
             .filter(invoice::state.eq(state))
-            .load::<Invoice>(conn)?)
+            .load::<Invoice>(conn)?))
+    }
+
+    pub fn get_by_payment_hash(
+        conn: &mut DbConnection,
+        payment_hash: &str,
+    ) -> anyhow::Result<Option<Invoice>> {
+        db_run!(conn, |conn| Ok(invoice::table
+            .filter(invoice::payment_hash.eq(payment_hash))
+            .first::<Invoice>(conn)
+            .optional()?))
+    }
+
+    /// The latest pending invoice of the user created for a callback with the same dedupe key.
+    pub fn get_pending_by_dedupe_key(
+        conn: &mut DbConnection,
+        user_id: i32,
+        dedupe_key: &str,
+    ) -> anyhow::Result<Option<Invoice>> {
+        db_run!(conn, |conn| Ok(invoice::table
+            .filter(invoice::user_id.eq(user_id))
+            .filter(invoice::dedupe_key.eq(dedupe_key))
+            .filter(invoice::state.eq(InvoiceState::Pending as i32))
+            .order(invoice::id.desc())
+            .first::<Invoice>(conn)
+            .optional()?))
     }
 
-    pub fn set_state(&self, conn: &mut PgConnection, s: i32) -> anyhow::Result<()> {
-        diesel::update(invoice::table)
-            .filter(invoice::id.eq(self.id))
-            .set(invoice::state.eq(s))
-            .execute(conn)?;
+    pub fn set_payment_hash(
+        &self,
+        conn: &mut DbConnection,
+        payment_hash: &str,
+    ) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(invoice::table)
+                .filter(invoice::id.eq(self.id))
+                .set(invoice::payment_hash.eq(payment_hash))
+                .execute(conn)?;
+        });
+
+        Ok(())
+    }
+
+    /// Frees the invoice's dedupe key for a new invoice.
+    pub fn clear_dedupe_key(&self, conn: &mut DbConnection) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(invoice::table)
+                .filter(invoice::id.eq(self.id))
+                .set(invoice::dedupe_key.eq(None::<String>))
+                .execute(conn)?;
+        });
+
+        Ok(())
+    }
+
+    pub fn set_state(&self, conn: &mut DbConnection, s: i32) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(invoice::table)
+                .filter(invoice::id.eq(self.id))
+                .set(invoice::state.eq(s))
+                .execute(conn)?;
+        });
 
         Ok(())
     }
@@ -60,18 +142,54 @@ pub struct NewInvoice {
     pub preimage: String,
     pub lnurlp_comment: Option<String>,
     pub state: i32,
+    pub receive_id: Option<String>,
+    pub pay_link_id: Option<i32>,
+    pub fiat_currency: Option<String>,
+    /// Fiat amount in the currency's smallest unit, when paid in or priced in fiat
+    pub fiat_amount: Option<i64>,
+    /// Price of one bitcoin in `fiat_currency` used for the conversion
+    pub fiat_rate: Option<f64>,
+    /// Hash of the callback that created the invoice, see [`Invoice::get_pending_by_dedupe_key`]
+    pub dedupe_key: Option<String>,
+    /// Hex payment hash of `bolt11`
+    pub payment_hash: Option<String>,
 }
 
 impl NewInvoice {
-    pub fn insert(&self, conn: &mut PgConnection) -> anyhow::Result<Invoice> {
-        diesel::insert_into(invoice::table)
-            .values(self)
-            .get_result::<Invoice>(conn)
-            .map_err(|e| e.into())
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<Invoice> {
+        self.insert_with_zap(conn, None)
+    }
+
+    /// Inserts the invoice and its zap request, if any, in a single transaction.
+    pub fn insert_with_zap(
+        &self,
+        conn: &mut DbConnection,
+        zap_request: Option<String>,
+    ) -> anyhow::Result<Invoice> {
+        db_run!(conn, |conn| conn.transaction::<_, anyhow::Error, _>(
+            |conn| {
+                let inserted = diesel::insert_into(invoice::table)
+                    .values(self)
+                    .get_result::<Invoice>(conn)?;
+
+                if let Some(request) = zap_request {
+                    diesel::insert_into(zaps::table)
+                        .values(Zap {
+                            id: inserted.id,
+                            request,
+                            event_id: None,
+                        })
+                        .execute(conn)?;
+                }
+
+                Ok(inserted)
+            }
+        ))
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
 #[repr(i32)]
 pub enum InvoiceState {
     /// The invoice is pending payment.
@@ -81,3 +199,23 @@ pub enum InvoiceState {
     /// The invoice has been cancelled or expired.
     Cancelled = 2,
 }
+
+impl InvoiceState {
+    /// Whether the invoice won't change state anymore.
+    pub fn is_final(self) -> bool {
+        self != Self::Pending
+    }
+}
+
+impl TryFrom<i32> for InvoiceState {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Pending),
+            1 => Ok(Self::Settled),
+            2 => Ok(Self::Cancelled),
+            _ => Err(anyhow::anyhow!("Invalid invoice state {value}")),
+        }
+    }
+}
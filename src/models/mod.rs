@@ -1,4 +1,43 @@
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::{PgConnection, SqliteConnection};
+
+pub mod banned_name;
+pub mod deposit;
+pub mod domain;
 pub mod invoice;
+pub mod pay_link;
 mod schema;
 pub mod user;
 pub mod zap;
+
+/// A pooled connection to whichever database backend is configured.
+///
+/// Model functions take this and run their queries through [`db_run!`],
+/// so every query is written once and compiled for each backend.
+pub enum DbConnection {
+    Postgres(PooledConnection<ConnectionManager<PgConnection>>),
+    Sqlite(PooledConnection<ConnectionManager<SqliteConnection>>),
+}
+
+/// Runs `$body` with `$conn` bound to the concrete diesel connection.
+macro_rules! db_run {
+    ($conn:expr, |$c:ident| $body:expr) => {
+        match $conn {
+            $crate::models::DbConnection::Postgres(pooled) => {
+                let $c: &mut diesel::PgConnection = &mut *pooled;
+                $body
+            }
+            $crate::models::DbConnection::Sqlite(pooled) => {
+                let $c: &mut diesel::SqliteConnection = &mut *pooled;
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use db_run;
+
+diesel::define_sql_function! {
+    /// SQL `LOWER`, user and banned names are compared case-insensitively.
+    fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+}
@@ -0,0 +1,99 @@
+use crate::models::invoice::InvoiceState;
+use crate::models::schema::{invoice, pay_links};
+use crate::models::{db_run, DbConnection};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A fixed-amount payment link owned by a user, e.g. "coffee" for 5000 sats,
+/// served at `/.well-known/lnurlp/{name}/{slug}`.
+#[derive(
+    QueryableByName, Queryable, AsChangeset, Serialize, Deserialize, Debug, Clone, PartialEq,
+)]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
+#[diesel(table_name = pay_links)]
+pub struct PayLink {
+    pub id: i32,
+    pub user_id: i32,
+    pub slug: String,
+    pub description: String,
+    /// For fiat priced links this is the amount at creation, the paid amount is converted at payment time
+    pub amount_msats: i64,
+    pub disabled: bool,
+    pub created_at: NaiveDateTime,
+    pub fiat_currency: Option<String>,
+    /// Price in the currency's smallest unit, e.g. cents
+    pub fiat_amount: Option<i64>,
+}
+
+impl PayLink {
+    pub fn get_by_user(conn: &mut DbConnection, user_id: i32) -> anyhow::Result<Vec<PayLink>> {
+        db_run!(conn, |conn| Ok(pay_links::table
+            .filter(pay_links::user_id.eq(user_id))
+            .load::<Self>(conn)?))
+    }
+
+    pub fn get_by_id(conn: &mut DbConnection, id: i32) -> anyhow::Result<Option<PayLink>> {
+        db_run!(conn, |conn| Ok(pay_links::table
+            .filter(pay_links::id.eq(id))
+            .first::<PayLink>(conn)
+            .optional()?))
+    }
+
+    pub fn get_by_slug(
+        conn: &mut DbConnection,
+        user_id: i32,
+        slug: &str,
+    ) -> anyhow::Result<Option<PayLink>> {
+        db_run!(conn, |conn| Ok(pay_links::table
+            .filter(pay_links::user_id.eq(user_id))
+            .filter(pay_links::slug.eq(slug))
+            .first::<PayLink>(conn)
+            .optional()?))
+    }
+
+    /// Disables the link, it is kept so invoices paid through it stay attributed.
+    pub fn set_disabled(&self, conn: &mut DbConnection, disabled: bool) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(pay_links::table)
+                .filter(pay_links::id.eq(self.id))
+                .set(pay_links::disabled.eq(disabled))
+                .execute(conn)?;
+        });
+
+        Ok(())
+    }
+
+    /// Number of settled invoices paid through this link and their total amount.
+    pub fn settled_totals(&self, conn: &mut DbConnection) -> anyhow::Result<(i64, i64)> {
+        db_run!(conn, |conn| {
+            let amounts = invoice::table
+                .filter(invoice::pay_link_id.eq(self.id))
+                .filter(invoice::state.eq(InvoiceState::Settled as i32))
+                .select(invoice::amount_msats)
+                .load::<i64>(conn)?;
+
+            Ok((amounts.len() as i64, amounts.iter().sum()))
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = pay_links)]
+pub struct NewPayLink {
+    pub user_id: i32,
+    pub slug: String,
+    pub description: String,
+    pub amount_msats: i64,
+    pub fiat_currency: Option<String>,
+    pub fiat_amount: Option<i64>,
+}
+
+impl NewPayLink {
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<PayLink> {
+        db_run!(conn, |conn| diesel::insert_into(pay_links::table)
+            .values(self)
+            .get_result::<PayLink>(conn)
+            .map_err(|e| e.into()))
+    }
+}
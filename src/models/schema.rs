@@ -1,5 +1,39 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    banned_names (name) {
+        #[max_length = 255]
+        name -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    deposits (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 128]
+        address -> Varchar,
+        #[max_length = 64]
+        txid -> Varchar,
+        vout -> Int4,
+        amount_sats -> Int8,
+        state -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    domains (id) {
+        id -> Int4,
+        #[max_length = 255]
+        domain -> Varchar,
+        min_sendable -> Int8,
+        max_sendable -> Int8,
+        comment_allowed -> Int4,
+    }
+}
+
 diesel::table! {
     invoice (id) {
         id -> Int4,
@@ -12,6 +46,34 @@ diesel::table! {
         #[max_length = 100]
         lnurlp_comment -> Nullable<Varchar>,
         state -> Int4,
+        #[max_length = 255]
+        receive_id -> Nullable<Varchar>,
+        pay_link_id -> Nullable<Int4>,
+        #[max_length = 3]
+        fiat_currency -> Nullable<Varchar>,
+        fiat_amount -> Nullable<Int8>,
+        fiat_rate -> Nullable<Float8>,
+        #[max_length = 64]
+        dedupe_key -> Nullable<Varchar>,
+        #[max_length = 64]
+        payment_hash -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    pay_links (id) {
+        id -> Int4,
+        user_id -> Int4,
+        #[max_length = 64]
+        slug -> Varchar,
+        #[max_length = 255]
+        description -> Varchar,
+        amount_msats -> Int8,
+        disabled -> Bool,
+        created_at -> Timestamp,
+        #[max_length = 3]
+        fiat_currency -> Nullable<Varchar>,
+        fiat_amount -> Nullable<Int8>,
     }
 }
 
@@ -23,6 +85,10 @@ diesel::table! {
         #[max_length = 255]
         name -> Varchar,
         disabled_zaps -> Bool,
+        disabled -> Bool,
+        domain_id -> Nullable<Int4>,
+        #[max_length = 128]
+        deposit_address -> Nullable<Varchar>,
     }
 }
 
@@ -35,7 +101,19 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(deposits -> users (user_id));
+diesel::joinable!(invoice -> pay_links (pay_link_id));
 diesel::joinable!(invoice -> users (user_id));
+diesel::joinable!(pay_links -> users (user_id));
+diesel::joinable!(users -> domains (domain_id));
 diesel::joinable!(zaps -> invoice (id));
 
-diesel::allow_tables_to_appear_in_same_query!(invoice, users, zaps,);
+diesel::allow_tables_to_appear_in_same_query!(
+    banned_names,
+    deposits,
+    domains,
+    invoice,
+    pay_links,
+    users,
+    zaps,
+);
@@ -1,4 +1,5 @@
 use crate::models::schema::users;
+use crate::models::{db_run, lower, DbConnection};
 use bitcoin::secp256k1::PublicKey;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -7,13 +8,19 @@ use std::str::FromStr;
 #[derive(
     QueryableByName, Queryable, AsChangeset, Serialize, Deserialize, Debug, Clone, PartialEq,
 )]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i32,
     pub pubkey: String,
     pub name: String,
     pub disabled_zaps: bool,
+    pub disabled: bool,
+    /// The [`Domain`](crate::models::domain::Domain) the name is registered under,
+    /// `None` for the primary domain
+    pub domain_id: Option<i32>,
+    /// Static on-chain deposit address of the user's wallet, created on first use
+    pub deposit_address: Option<String>,
 }
 
 impl User {
@@ -21,47 +28,159 @@ impl User {
         PublicKey::from_str(&self.pubkey).expect("invalid pubkey")
     }
 
-    pub fn get_users(conn: &mut PgConnection) -> anyhow::Result<Vec<User>> {
-        Ok(users::table.load::<Self>(conn)?)
+    pub fn get_users(conn: &mut DbConnection) -> anyhow::Result<Vec<User>> {
+        db_run!(conn, |conn| Ok(users::table.load::<Self>(conn)?))
     }
 
-    pub fn get_by_id(conn: &mut PgConnection, user_id: i32) -> anyhow::Result<Option<User>> {
-        Ok(users::table
+    /// Users that were given a deposit address.
+    pub fn get_with_deposit_address(conn: &mut DbConnection) -> anyhow::Result<Vec<User>> {
+        db_run!(conn, |conn| Ok(users::table
+            .filter(users::deposit_address.is_not_null())
+            .load::<Self>(conn)?))
+    }
+
+    pub fn get_by_id(conn: &mut DbConnection, user_id: i32) -> anyhow::Result<Option<User>> {
+        db_run!(conn, |conn| Ok(users::table
             .filter(users::id.eq(user_id))
             .first::<User>(conn)
-            .optional()?)
+            .optional()?))
     }
 
-    pub fn get_by_name(conn: &mut PgConnection, name: &str) -> anyhow::Result<Option<User>> {
-        Ok(users::table
-            .filter(users::name.eq(name))
-            .first::<User>(conn)
-            .optional()?)
+    /// Looks up `name` case-insensitively within a domain, `None` being the primary domain.
+    pub fn get_by_name(
+        conn: &mut DbConnection,
+        domain_id: Option<i32>,
+        name: &str,
+    ) -> anyhow::Result<Option<User>> {
+        db_run!(conn, |conn| {
+            let query = users::table
+                .filter(lower(users::name).eq(name.to_lowercase()))
+                .into_boxed();
+            let query = match domain_id {
+                Some(id) => query.filter(users::domain_id.eq(id)),
+                None => query.filter(users::domain_id.is_null()),
+            };
+
+            Ok(query.first::<User>(conn).optional()?)
+        })
+    }
+
+    /// Every user named `name`, case-insensitively, across all domains.
+    pub fn get_all_by_name(conn: &mut DbConnection, name: &str) -> anyhow::Result<Vec<User>> {
+        db_run!(conn, |conn| Ok(users::table
+            .filter(lower(users::name).eq(name.to_lowercase()))
+            .load::<User>(conn)?))
+    }
+
+    /// Users matching `filter`, ordered by id.
+    pub fn search(conn: &mut DbConnection, filter: &UserFilter) -> anyhow::Result<Vec<User>> {
+        db_run!(conn, |conn| {
+            let mut query = users::table.order(users::id).into_boxed();
+            match filter.domain_id {
+                Some(Some(id)) => query = query.filter(users::domain_id.eq(id)),
+                Some(None) => query = query.filter(users::domain_id.is_null()),
+                None => {}
+            }
+            if let Some(q) = filter.query.as_deref().filter(|q| !q.is_empty()) {
+                let pattern = format!(
+                    "%{}%",
+                    q.to_lowercase()
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                );
+                query = query.filter(
+                    lower(users::name)
+                        .like(pattern)
+                        .escape('\\')
+                        .or(users::pubkey.eq(q)),
+                );
+            }
+
+            Ok(query.load::<User>(conn)?)
+        })
+    }
+
+    /// Number of users and how many of them are disabled.
+    pub fn count(conn: &mut DbConnection) -> anyhow::Result<(i64, i64)> {
+        db_run!(conn, |conn| {
+            let total = users::table.count().get_result::<i64>(conn)?;
+            let disabled = users::table
+                .filter(users::disabled.eq(true))
+                .count()
+                .get_result::<i64>(conn)?;
+
+            Ok((total, disabled))
+        })
     }
 
-    pub fn check_available_name(conn: &mut PgConnection, name: String) -> anyhow::Result<bool> {
-        Ok(users::table
-            .filter(users::name.eq(name))
+    /// Number of users registered under a secondary domain.
+    pub fn count_in_domain(conn: &mut DbConnection, domain_id: i32) -> anyhow::Result<i64> {
+        db_run!(conn, |conn| Ok(users::table
+            .filter(users::domain_id.eq(domain_id))
             .count()
-            .get_result::<i64>(conn)?
-            == 0)
+            .get_result::<i64>(conn)?))
     }
 
-    pub fn get_by_pubkey(conn: &mut PgConnection, pubkey: String) -> anyhow::Result<Option<User>> {
-        Ok(users::table
-            .filter(users::pubkey.eq(pubkey))
+    pub fn check_available_name(
+        conn: &mut DbConnection,
+        domain_id: Option<i32>,
+        name: String,
+    ) -> anyhow::Result<bool> {
+        Ok(Self::get_by_name(conn, domain_id, &name)?.is_none())
+    }
+
+    pub fn get_by_pubkey(conn: &mut DbConnection, pubkey: String) -> anyhow::Result<Option<User>> {
+        db_run!(conn, |conn| Ok(users::table
+            .filter(users::pubkey.eq(&pubkey))
             .first::<User>(conn)
-            .optional()?)
+            .optional()?))
+    }
+
+    pub fn set_deposit_address(
+        &self,
+        conn: &mut DbConnection,
+        address: &str,
+    ) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(users::table)
+                .filter(users::id.eq(self.id))
+                .set(users::deposit_address.eq(address))
+                .execute(conn)?;
+        });
+
+        Ok(())
     }
 
-    pub fn disable_zaps(&self, conn: &mut PgConnection) -> anyhow::Result<()> {
-        diesel::update(users::table)
-            .filter(users::name.eq(&self.name))
-            .set((users::disabled_zaps.eq(true),))
-            .execute(conn)?;
+    pub fn set_disabled(&self, conn: &mut DbConnection, disabled: bool) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(users::table)
+                .filter(users::id.eq(self.id))
+                .set(users::disabled.eq(disabled))
+                .execute(conn)?;
+        });
 
         Ok(())
     }
+
+    /// Deletes the user, freeing up the name. Their invoices, zaps, pay links and
+    /// deposits are kept as history.
+    pub fn delete(&self, conn: &mut DbConnection) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::delete(users::table.filter(users::id.eq(self.id))).execute(conn)?;
+        });
+
+        Ok(())
+    }
+}
+
+/// Filters for [`User::search`], unset fields match every user.
+#[derive(Debug, Default, Clone)]
+pub struct UserFilter {
+    /// Only users of this domain, `Some(None)` being the primary domain
+    pub domain_id: Option<Option<i32>>,
+    /// Case-insensitive substring of the name, or an exact pubkey
+    pub query: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -69,13 +188,14 @@ impl User {
 pub struct NewUser {
     pub pubkey: String,
     pub name: String,
+    pub domain_id: Option<i32>,
 }
 
 impl NewUser {
-    pub fn insert(&self, conn: &mut PgConnection) -> anyhow::Result<User> {
-        diesel::insert_into(users::table)
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<User> {
+        db_run!(conn, |conn| diesel::insert_into(users::table)
             .values(self)
             .get_result::<User>(conn)
-            .map_err(|e| e.into())
+            .map_err(|e| e.into()))
     }
 }
@@ -1,4 +1,5 @@
 use crate::models::schema::zaps;
+use crate::models::{db_run, DbConnection};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -13,7 +14,7 @@ use serde::{Deserialize, Serialize};
     Clone,
     PartialEq,
 )]
-#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(check_for_backend(diesel::pg::Pg, diesel::sqlite::Sqlite))]
 #[diesel(table_name = zaps)]
 pub struct Zap {
     pub id: i32,
@@ -22,30 +23,40 @@ pub struct Zap {
 }
 
 impl Zap {
-    pub fn insert(&self, conn: &mut PgConnection) -> anyhow::Result<Zap> {
-        let res = diesel::insert_into(zaps::table)
-            .values(self)
-            .get_result(conn)?;
+    pub fn insert(&self, conn: &mut DbConnection) -> anyhow::Result<Zap> {
+        db_run!(conn, |conn| {
+            let res = diesel::insert_into(zaps::table)
+                .values(self)
+                .get_result(conn)?;
 
-        Ok(res)
+            Ok(res)
+        })
     }
 
-    pub fn get_zaps(conn: &mut PgConnection) -> anyhow::Result<Vec<Zap>> {
-        Ok(zaps::table.load::<Self>(conn)?)
+    pub fn get_zaps(conn: &mut DbConnection) -> anyhow::Result<Vec<Zap>> {
+        db_run!(conn, |conn| Ok(zaps::table.load::<Self>(conn)?))
     }
 
-    pub fn get_by_id(conn: &mut PgConnection, zap_id: i32) -> anyhow::Result<Option<Zap>> {
-        Ok(zaps::table
+    pub fn count(conn: &mut DbConnection) -> anyhow::Result<i64> {
+        db_run!(conn, |conn| Ok(zaps::table
+            .count()
+            .get_result::<i64>(conn)?))
+    }
+
+    pub fn get_by_id(conn: &mut DbConnection, zap_id: i32) -> anyhow::Result<Option<Zap>> {
+        db_run!(conn, |conn| Ok(zaps::table
             .filter(zaps::id.eq(zap_id))
             .first::<Zap>(conn)
-            .optional()?)
+            .optional()?))
     }
 
-    pub fn set_event_id(&self, conn: &mut PgConnection, event_id: String) -> anyhow::Result<()> {
-        diesel::update(zaps::table)
-            .filter(zaps::id.eq(self.id))
-            .set(zaps::event_id.eq(event_id))
-            .execute(conn)?;
+    pub fn set_event_id(&self, conn: &mut DbConnection, event_id: String) -> anyhow::Result<()> {
+        db_run!(conn, |conn| {
+            diesel::update(zaps::table)
+                .filter(zaps::id.eq(self.id))
+                .set(zaps::event_id.eq(&event_id))
+                .execute(conn)?;
+        });
 
         Ok(())
     }
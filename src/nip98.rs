@@ -0,0 +1,159 @@
+use crate::domains::{domain_url, resolve_domain};
+use crate::error::ApiError;
+use crate::State;
+use anyhow::anyhow;
+use axum::async_trait;
+use axum::extract::{FromRequestParts, Host, OriginalUri};
+use axum::http::header;
+use axum::http::request::Parts;
+use base64::Engine;
+use nostr::{Event, JsonUtil, Kind, PublicKey, Timestamp};
+use tracing::warn;
+
+/// How far a NIP-98 auth event's `created_at` may drift from our clock, in seconds.
+const MAX_AGE_SECS: u64 = 60;
+
+/// Verifies the base64 NIP-98 event of an `Authorization: Nostr` header against the
+/// request it came with, returning the pubkey that signed it. The url is expected on the
+/// domain the request was sent to.
+pub async fn verify_request(
+    state: &State,
+    parts: &mut Parts,
+    encoded: &str,
+) -> anyhow::Result<PublicKey> {
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map(|u| u.0.clone())
+        .unwrap_or_else(|| parts.uri.clone());
+    let host = Host::from_request_parts(parts, state).await.ok();
+    let domain = resolve_domain(state, host.as_ref().map(|h| h.0.as_str())).await?;
+    // only the path and query, absolute-form request targets also carry a scheme and host
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+    let url = domain_url(state, &domain, path);
+
+    verify(encoded, &url, parts.method.as_str())
+}
+
+/// Verifies a NIP-98 HTTP auth event for the given absolute url and method.
+pub fn verify(encoded: &str, url: &str, method: &str) -> anyhow::Result<PublicKey> {
+    let json = base64::engine::general_purpose::STANDARD.decode(encoded)?;
+    let event = Event::from_json(json)?;
+    event.verify()?;
+
+    if event.kind != Kind::HttpAuth {
+        return Err(anyhow!("Invalid event kind"));
+    }
+
+    let now = Timestamp::now().as_u64();
+    if now.abs_diff(event.created_at.as_u64()) > MAX_AGE_SECS {
+        return Err(anyhow!("Auth event expired"));
+    }
+
+    let tag_value = |name: &str| {
+        event.tags.iter().find_map(|t| match t.as_slice() {
+            [k, v, ..] if k == name => Some(v.clone()),
+            _ => None,
+        })
+    };
+
+    if tag_value("u").as_deref() != Some(url) {
+        return Err(anyhow!("Auth event url mismatch"));
+    }
+
+    if !tag_value("method").is_some_and(|m| m.eq_ignore_ascii_case(method)) {
+        return Err(anyhow!("Auth event method mismatch"));
+    }
+
+    Ok(event.pubkey)
+}
+
+/// Extractor for the pubkey that signed the request's NIP-98 `Authorization: Nostr` header.
+pub struct Nip98Auth(pub PublicKey);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Nip98Auth
+where
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let state = parts
+            .extensions
+            .get::<State>()
+            .cloned()
+            .ok_or(ApiError::Unauthorized)?;
+
+        let encoded = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Nostr "))
+            .map(str::to_owned)
+            .ok_or(ApiError::Unauthorized)?;
+
+        match verify_request(&state, parts, &encoded).await {
+            Ok(pubkey) => Ok(Nip98Auth(pubkey)),
+            Err(e) => {
+                warn!("Rejected NIP-98 auth: {e}");
+                Err(ApiError::Unauthorized)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventBuilder, Keys, Tag};
+
+    const URL: &str = "https://example.com/admin/users";
+
+    fn auth_event(keys: &Keys, kind: Kind, url: &str, method: &str, created_at: u64) -> String {
+        let event = EventBuilder::new(kind, "")
+            .tag(Tag::parse(["u", url]).unwrap())
+            .tag(Tag::parse(["method", method]).unwrap())
+            .custom_created_at(Timestamp::from(created_at))
+            .sign_with_keys(keys)
+            .unwrap();
+        base64::engine::general_purpose::STANDARD.encode(event.as_json())
+    }
+
+    #[test]
+    fn verifies_auth_events() {
+        let keys = Keys::generate();
+        let now = Timestamp::now().as_u64();
+
+        let valid = auth_event(&keys, Kind::HttpAuth, URL, "GET", now);
+        assert_eq!(verify(&valid, URL, "GET").unwrap(), keys.public_key());
+        assert_eq!(verify(&valid, URL, "get").unwrap(), keys.public_key());
+
+        let rejected = [
+            (
+                auth_event(&keys, Kind::HttpAuth, URL, "GET", now),
+                "POST",
+                URL,
+            ),
+            (
+                auth_event(&keys, Kind::HttpAuth, URL, "GET", now),
+                "GET",
+                "https://example.com/admin/stats",
+            ),
+            (
+                auth_event(&keys, Kind::TextNote, URL, "GET", now),
+                "GET",
+                URL,
+            ),
+            (
+                auth_event(&keys, Kind::HttpAuth, URL, "GET", now - 120),
+                "GET",
+                URL,
+            ),
+            ("not base64".to_string(), "GET", URL),
+        ];
+        for (encoded, method, url) in rejected {
+            assert!(verify(&encoded, url, method).is_err());
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How often prices are refetched from an HTTP source.
+const PRICE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// Prices older than this aren't used for conversions, e.g. when the source has been down for a while.
+const PRICE_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// Source of bitcoin exchange rates used to convert fiat amounts.
+#[async_trait::async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Price of one bitcoin in `currency`, e.g. `USD`.
+    async fn btc_price(&self, currency: &str) -> anyhow::Result<f64>;
+}
+
+/// [`PriceSource`] with fixed rates, for testing and regtest setups.
+pub struct FixedRateSource {
+    rates: HashMap<String, f64>,
+}
+
+impl FixedRateSource {
+    pub fn new(rates: HashMap<String, f64>) -> Self {
+        Self { rates }
+    }
+
+    /// Parses rates given as `CODE:PRICE`, e.g. `USD:60000`.
+    pub fn parse(rates: &[String]) -> anyhow::Result<Self> {
+        let rates = rates
+            .iter()
+            .map(|r| {
+                let (code, price) = r
+                    .split_once(':')
+                    .ok_or(anyhow!("Invalid rate {r}, expected CODE:PRICE"))?;
+                Ok((code.to_uppercase(), f64::from_str(price)?))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self::new(rates))
+    }
+}
+
+#[async_trait::async_trait]
+impl PriceSource for FixedRateSource {
+    async fn btc_price(&self, currency: &str) -> anyhow::Result<f64> {
+        self.rates
+            .get(currency)
+            .copied()
+            .ok_or(anyhow!("No rate for {currency}"))
+    }
+}
+
+/// [`PriceSource`] backed by a mempool.space style `/api/v1/prices` endpoint,
+/// which returns the bitcoin price keyed by currency code.
+///
+/// Prices are fetched by [`HttpPriceSource::start_refresh`], lookups only read the last fetched prices.
+pub struct HttpPriceSource {
+    url: String,
+    client: reqwest::Client,
+    prices: RwLock<Option<(Instant, HashMap<String, f64>)>>,
+}
+
+impl HttpPriceSource {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::new(),
+            prices: RwLock::new(None),
+        }
+    }
+
+    /// Refetches prices every [`PRICE_REFRESH_INTERVAL`].
+    pub async fn start_refresh(self: Arc<Self>) {
+        loop {
+            match self.fetch().await {
+                Ok(prices) => *self.prices.write().unwrap() = Some((Instant::now(), prices)),
+                Err(e) => warn!("Failed to fetch prices from {}: {e}", self.url),
+            }
+            tokio::time::sleep(PRICE_REFRESH_INTERVAL).await;
+        }
+    }
+
+    async fn fetch(&self) -> anyhow::Result<HashMap<String, f64>> {
+        let body: HashMap<String, serde_json::Value> = self
+            .client
+            .get(&self.url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(body
+            .into_iter()
+            .filter_map(|(code, price)| Some((code.to_uppercase(), price.as_f64()?)))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl PriceSource for HttpPriceSource {
+    async fn btc_price(&self, currency: &str) -> anyhow::Result<f64> {
+        let prices = self.prices.read().unwrap();
+        let (fetched_at, prices) = prices.as_ref().ok_or(anyhow!("Prices not fetched yet"))?;
+        if fetched_at.elapsed() > PRICE_MAX_AGE {
+            return Err(anyhow!("Prices are out of date"));
+        }
+
+        prices
+            .get(currency)
+            .copied()
+            .ok_or(anyhow!("No rate for {currency}"))
+    }
+}
+
+/// Currencies we know how to display, as (code, name, symbol, decimals).
+const KNOWN_CURRENCIES: &[(&str, &str, &str, u32)] = &[
+    ("USD", "US Dollar", "$", 2),
+    ("EUR", "Euro", "€", 2),
+    ("GBP", "British Pound", "£", 2),
+    ("CAD", "Canadian Dollar", "$", 2),
+    ("CHF", "Swiss Franc", "CHF", 2),
+    ("AUD", "Australian Dollar", "$", 2),
+    ("JPY", "Japanese Yen", "¥", 0),
+];
+
+/// A fiat currency payers may denominate amounts in, as advertised in LUD-21 `currencies`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Currency {
+    pub code: String,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    /// Millisatoshis per smallest unit of the currency, set when advertised
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multiplier: Option<f64>,
+    pub convertible: bool,
+}
+
+impl Currency {
+    pub fn from_code(code: &str) -> anyhow::Result<Self> {
+        let code = code.to_uppercase();
+        let (_, name, symbol, decimals) = KNOWN_CURRENCIES
+            .iter()
+            .find(|(c, ..)| *c == code)
+            .ok_or(anyhow!("Unsupported currency {code}"))?;
+
+        Ok(Self {
+            code,
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            decimals: *decimals,
+            multiplier: None,
+            convertible: true,
+        })
+    }
+
+    /// Millisatoshis per smallest unit (e.g. cents) at the given bitcoin price.
+    pub fn msats_per_unit(&self, btc_price: f64) -> f64 {
+        100_000_000_000.0 / btc_price / 10f64.powi(self.decimals as i32)
+    }
+
+    /// Converts `amount` smallest units to millisatoshis, rounded to whole sats.
+    pub fn to_msats(&self, amount: u64, btc_price: f64) -> u64 {
+        let msats = amount as f64 * self.msats_per_unit(btc_price);
+        ((msats / 1_000.0).round() as u64) * 1_000
+    }
+}
+
+/// The fiat side of a conversion, recorded on invoices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiatQuote {
+    pub currency: String,
+    /// Amount in the currency's smallest unit
+    pub amount: u64,
+    /// Price of one bitcoin in the currency at conversion time
+    pub btc_price: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_to_whole_sats() {
+        let usd = Currency::from_code("usd").unwrap();
+        // 1 cent at $100,000 per bitcoin is 10 sats
+        assert_eq!(usd.msats_per_unit(100_000.0), 10_000.0);
+        assert_eq!(usd.to_msats(500, 100_000.0), 5_000_000);
+        // 1 cent at $60,000 is 16.67 sats
+        assert_eq!(usd.to_msats(1, 60_000.0), 17_000);
+        assert_eq!(usd.to_msats(2, 60_000.0), 33_000);
+
+        let jpy = Currency::from_code("JPY").unwrap();
+        assert_eq!(jpy.to_msats(1_000, 10_000_000.0), 10_000_000);
+    }
+
+    #[test]
+    fn unknown_currency() {
+        assert!(Currency::from_code("XYZ").is_err());
+    }
+
+    #[tokio::test]
+    async fn fixed_rates() {
+        let source = FixedRateSource::parse(&["usd:60000".to_string()]).unwrap();
+        assert_eq!(source.btc_price("USD").await.unwrap(), 60_000.0);
+        assert!(source.btc_price("EUR").await.is_err());
+
+        assert!(FixedRateSource::parse(&["USD".to_string()]).is_err());
+        assert!(FixedRateSource::parse(&["USD:abc".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn http_source_serves_fetched_prices() {
+        let source = HttpPriceSource::new("http://localhost".to_string());
+        assert!(source.btc_price("USD").await.is_err());
+
+        let prices = HashMap::from([("USD".to_string(), 60_000.0)]);
+        *source.prices.write().unwrap() = Some((Instant::now(), prices.clone()));
+        assert_eq!(source.btc_price("USD").await.unwrap(), 60_000.0);
+        assert!(source.btc_price("EUR").await.is_err());
+
+        let stale = Instant::now() - PRICE_MAX_AGE - Duration::from_secs(1);
+        *source.prices.write().unwrap() = Some((stale, prices));
+        assert!(source.btc_price("USD").await.is_err());
+    }
+}
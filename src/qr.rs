@@ -0,0 +1,51 @@
+use crate::domains::resolve_domain;
+use crate::error::ApiError;
+use crate::routes::encode_lnurl;
+use crate::State;
+use axum::extract::{Host, Path};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use image::{DynamicImage, ImageFormat, Luma};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use std::io::Cursor;
+
+/// HTTP endpoint rendering a user's LNURL as a QR code, served at `/qr/{name}.svg` or `/qr/{name}.png`.
+pub async fn get_qr(
+    Path(file): Path<String>,
+    host: Option<Host>,
+    Extension(state): Extension<State>,
+) -> Result<Response, ApiError> {
+    let (name, ext) = file.rsplit_once('.').ok_or_else(unsupported_format)?;
+    if ext != "svg" && ext != "png" {
+        return Err(unsupported_format());
+    }
+
+    let domain = resolve_domain(&state, host.as_ref().map(|h| h.0.as_str())).await?;
+    let lnurl = encode_lnurl(&state, &domain, name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?
+        .lnurl;
+
+    // uppercase fits the QR alphanumeric mode, making for a smaller code
+    let code =
+        QrCode::new(lnurl.to_uppercase().as_bytes()).map_err(|e| ApiError::Internal(e.into()))?;
+
+    if ext == "svg" {
+        let image = code.render::<svg::Color>().min_dimensions(256, 256).build();
+        return Ok(([(header::CONTENT_TYPE, "image/svg+xml")], image).into_response());
+    }
+
+    let image = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
+    let mut buf = Vec::new();
+    DynamicImage::ImageLuma8(image)
+        .write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)
+        .map_err(|e| ApiError::Internal(e.into()))?;
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], buf).into_response())
+}
+
+fn unsupported_format() -> ApiError {
+    ApiError::InvalidRequest("QR codes are served as .svg or .png".to_string())
+}
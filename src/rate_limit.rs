@@ -0,0 +1,445 @@
+use crate::error::ApiError;
+use crate::State;
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Max keys a limiter tracks, past this the least recently used bucket is dropped once
+/// it has refilled.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Period the LNURL lookup and callback limits are per.
+const LOOKUP_PERIOD: Duration = Duration::from_secs(60);
+
+/// Period the registration limit is per.
+const REGISTER_PERIOD: Duration = Duration::from_secs(3600);
+
+/// Source of time for rate limiters, so buckets can be driven by a fake clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// When the bucket was last used, its key in [`Buckets::lru`]
+    seq: u64,
+}
+
+impl TokenBucket {
+    /// Tokens in the bucket at `now`.
+    fn tokens_at(&self, now: Instant, capacity: f64, rate: f64) -> f64 {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        (self.tokens + elapsed * rate).min(capacity)
+    }
+}
+
+/// A keyed token-bucket rate limiter.
+///
+/// Each key gets a bucket of `capacity` tokens that refills continuously over `period`.
+/// A limiter with a capacity of 0 allows everything.
+pub struct RateLimiter<K, C = SystemClock> {
+    clock: C,
+    inner: Mutex<Buckets<K>>,
+}
+
+struct Buckets<K> {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: HashMap<K, TokenBucket>,
+    /// Keys ordered by when their bucket was last used, oldest first
+    lru: BTreeMap<u64, K>,
+    next_seq: u64,
+}
+
+impl<K: Eq + Hash + Clone> RateLimiter<K> {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Self::with_clock(capacity, period, SystemClock)
+    }
+}
+
+impl<K: Eq + Hash + Clone, C: Clock> RateLimiter<K, C> {
+    pub fn with_clock(capacity: u32, period: Duration, clock: C) -> Self {
+        Self {
+            clock,
+            inner: Mutex::new(Buckets {
+                capacity: capacity as f64,
+                refill_per_sec: capacity as f64 / period.as_secs_f64(),
+                buckets: HashMap::new(),
+                lru: BTreeMap::new(),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// Changes the limit, existing buckets keep their tokens up to the new capacity.
+    pub fn set_capacity(&self, capacity: u32, period: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity as f64;
+        inner.refill_per_sec = capacity as f64 / period.as_secs_f64();
+        for bucket in inner.buckets.values_mut() {
+            bucket.tokens = bucket.tokens.min(capacity as f64);
+        }
+    }
+
+    /// Takes a token for `key`, or returns how long until one is available.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        let (capacity, rate) = (inner.capacity, inner.refill_per_sec);
+        if capacity == 0.0 {
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        let Buckets {
+            buckets,
+            lru,
+            next_seq,
+            ..
+        } = &mut *inner;
+
+        match buckets.get(&key) {
+            Some(bucket) => {
+                lru.remove(&bucket.seq);
+            }
+            None if buckets.len() >= MAX_TRACKED_KEYS => {
+                // A refilled bucket is no different from a new one, so dropping it forgets
+                // nothing. Every bucket refills within a period of its last use, so if the
+                // least recently used one hasn't, new keys wait rather than wiping out debt.
+                let (&oldest_seq, oldest) = lru.first_key_value().expect("keys are tracked");
+                let tokens = buckets[oldest].tokens_at(now, capacity, rate);
+                if tokens < capacity {
+                    return Err(Duration::from_secs_f64((capacity - tokens) / rate));
+                }
+                if let Some(oldest) = lru.remove(&oldest_seq) {
+                    buckets.remove(&oldest);
+                }
+            }
+            None => {}
+        }
+
+        let seq = *next_seq;
+        *next_seq += 1;
+        lru.insert(seq, key.clone());
+
+        let bucket = buckets.entry(key).or_insert(TokenBucket {
+            tokens: capacity,
+            last_refill: now,
+            seq,
+        });
+        bucket.seq = seq;
+        bucket.tokens = bucket.tokens_at(now, capacity, rate);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / rate;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+/// The rate limiters applied to the public endpoints.
+pub struct RateLimits {
+    /// Per client IP or IPv6 /64, shared by LNURL lookups and callbacks
+    pub ip: RateLimiter<IpAddr>,
+    /// Per target user name, shared by LNURL lookups and callbacks
+    pub name: RateLimiter<String>,
+    /// Per client IP or IPv6 /64, for registrations
+    pub register: RateLimiter<IpAddr>,
+}
+
+impl RateLimits {
+    /// Limits per minute for `ip` and `name`, and per hour for `register`.
+    pub fn new(ip: u32, name: u32, register: u32) -> Self {
+        Self {
+            ip: RateLimiter::new(ip, LOOKUP_PERIOD),
+            name: RateLimiter::new(name, LOOKUP_PERIOD),
+            register: RateLimiter::new(register, REGISTER_PERIOD),
+        }
+    }
+
+    /// Applies new limits in place, used when the config is reloaded.
+    pub fn set_limits(&self, ip: u32, name: u32, register: u32) {
+        self.ip.set_capacity(ip, LOOKUP_PERIOD);
+        self.name.set_capacity(name, LOOKUP_PERIOD);
+        self.register.set_capacity(register, REGISTER_PERIOD);
+    }
+}
+
+/// The IP address of the client making the request.
+///
+/// Uses the last `X-Forwarded-For` entry when `trust_proxy` is enabled, the one our proxy
+/// appended, as anything before it is up to the client. Otherwise the address of the
+/// connecting socket.
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let trust_proxy = parts
+            .extensions
+            .get::<State>()
+            .is_some_and(|s| s.trust_proxy);
+
+        if let Some(ip) = trust_proxy.then(|| forwarded_ip(&parts.headers)).flatten() {
+            return Ok(ClientIp(ip));
+        }
+
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|c| c.0.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+        Ok(ClientIp(ip))
+    }
+}
+
+/// The address our proxy appended to `X-Forwarded-For`.
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get("x-forwarded-for")?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// The key a client IP is limited by. IPv6 clients typically get a whole /64, so they
+/// share a bucket per /64 rather than getting a fresh one for every address in it.
+fn ip_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(
+                u128::from(v6) & 0xffff_ffff_ffff_ffff_0000_0000_0000_0000,
+            )),
+        },
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    ApiError::RateLimited(retry_after).into_response()
+}
+
+/// Middleware limiting requests per client IP.
+pub async fn limit_by_ip<B>(
+    Extension(state): Extension<State>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match state.rate_limits.ip.check(ip_key(ip)) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+/// Middleware limiting requests per target user, for routes with a `:name` parameter.
+pub async fn limit_by_name<B>(
+    Extension(state): Extension<State>,
+    Path(params): Path<Vec<String>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let name = params.first().map(|n| n.to_lowercase()).unwrap_or_default();
+    match state.rate_limits.name.check(name) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+/// Middleware limiting registrations per client IP.
+pub async fn limit_registrations<B>(
+    Extension(state): Extension<State>,
+    ClientIp(ip): ClientIp,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match state.rate_limits.register.check(ip_key(ip)) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => too_many_requests(retry_after),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, HeaderValue, StatusCode};
+    use std::sync::Arc;
+
+    #[derive(Clone)]
+    struct FakeClock(Arc<Mutex<Instant>>);
+
+    impl FakeClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn limiter(capacity: u32) -> (RateLimiter<u32, FakeClock>, FakeClock) {
+        let clock = FakeClock::new();
+        let limiter = RateLimiter::with_clock(capacity, Duration::from_secs(60), clock.clone());
+        (limiter, clock)
+    }
+
+    #[test]
+    fn refills_over_the_period() {
+        let (limiter, clock) = limiter(2);
+
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_ok());
+        let wait = limiter.check(1).unwrap_err();
+        assert_eq!(wait.as_secs(), 30);
+
+        // other keys have their own bucket
+        assert!(limiter.check(2).is_ok());
+
+        clock.advance(Duration::from_secs(29));
+        assert!(limiter.check(1).is_err());
+        clock.advance(Duration::from_secs(2));
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_err());
+
+        // a full period refills the bucket but not past capacity
+        clock.advance(Duration::from_secs(600));
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_err());
+    }
+
+    #[test]
+    fn zero_capacity_allows_everything() {
+        let (limiter, _) = limiter(0);
+        for _ in 0..1_000 {
+            assert!(limiter.check(1).is_ok());
+        }
+    }
+
+    #[test]
+    fn set_capacity_applies_to_existing_buckets() {
+        let (limiter, clock) = limiter(5);
+        assert!(limiter.check(1).is_ok());
+
+        limiter.set_capacity(1, Duration::from_secs(60));
+        assert!(limiter.check(1).is_ok());
+        assert_eq!(limiter.check(1).unwrap_err().as_secs(), 60);
+
+        limiter.set_capacity(0, Duration::from_secs(60));
+        assert!(limiter.check(1).is_ok());
+
+        limiter.set_capacity(3, Duration::from_secs(60));
+        clock.advance(Duration::from_secs(60));
+        for _ in 0..3 {
+            assert!(limiter.check(1).is_ok());
+        }
+        assert!(limiter.check(1).is_err());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_keys() {
+        let (limiter, clock) = limiter(1);
+        for key in 0..MAX_TRACKED_KEYS as u32 {
+            assert!(limiter.check(key).is_ok());
+        }
+        // every bucket is empty, dropping one would hand its key a fresh token
+        let new_key = MAX_TRACKED_KEYS as u32;
+        assert_eq!(limiter.check(new_key).unwrap_err().as_secs(), 60);
+
+        clock.advance(Duration::from_secs(60));
+        // touching key 0 leaves key 1 as the least recently used one
+        assert!(limiter.check(0).is_ok());
+        assert!(limiter.check(new_key).is_ok());
+
+        let inner = limiter.inner.lock().unwrap();
+        assert_eq!(inner.buckets.len(), MAX_TRACKED_KEYS);
+        assert_eq!(inner.lru.len(), MAX_TRACKED_KEYS);
+        assert!(inner.buckets.contains_key(&0));
+        assert!(!inner.buckets.contains_key(&1));
+        drop(inner);
+
+        // the evicted key starts over with a full bucket
+        assert!(limiter.check(1).is_ok());
+        assert!(limiter.check(1).is_err());
+    }
+
+    #[test]
+    fn rate_limited_response_has_retry_after() {
+        let resp = too_many_requests(Duration::from_millis(1_500));
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "2");
+
+        let resp = too_many_requests(Duration::from_millis(10));
+        assert_eq!(resp.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[test]
+    fn ipv6_clients_are_limited_per_64() {
+        let ip = |s: &str| ip_key(s.parse().unwrap());
+        assert_eq!(ip("203.0.113.7"), ip("203.0.113.7"));
+        assert_ne!(ip("203.0.113.7"), ip("203.0.113.8"));
+        assert_eq!(ip("2001:db8:1:2::1"), ip("2001:db8:1:2:ffff::9"));
+        assert_eq!(ip("2001:db8:1:2::1"), ip("2001:db8:1:2::"));
+        assert_ne!(ip("2001:db8:1:2::1"), ip("2001:db8:1:3::1"));
+        assert_eq!(ip("::ffff:203.0.113.7"), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn uses_the_last_forwarded_address() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_ip(&headers), None);
+
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.1.1.1, 10.0.0.1,203.0.113.7"),
+        );
+        assert_eq!(forwarded_ip(&headers), Some("203.0.113.7".parse().unwrap()));
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("1.1.1.1, junk"));
+        assert_eq!(forwarded_ip(&headers), None);
+    }
+}
@@ -1,33 +1,171 @@
+use crate::backend::{BackendError, PaymentStatus};
+use crate::domains::{domain_url, lookup_domain, resolve_domain, DomainSettings};
+use crate::error::ApiError;
+use crate::invoice_events;
+use crate::logging;
 use crate::models::invoice::{InvoiceState, NewInvoice};
+use crate::models::pay_link::PayLink;
 use crate::models::user::{NewUser, User};
-use crate::models::zap::Zap;
+use crate::price::{Currency, FiatQuote};
+use crate::storage::is_unique_violation;
 use crate::State;
-use anyhow::anyhow;
-use axum::extract::{Path, Query};
-use axum::http::{StatusCode, Uri};
+use axum::extract::{Host, Path, Query};
+use axum::http::Uri;
 use axum::{Extension, Json};
 use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::PublicKey;
-use diesel::Connection;
+use bitcoin::{Amount, Denomination};
 use lightning_invoice::Bolt11Invoice;
+use lnurl::lnurl::LnUrl;
 use lnurl::pay::PayResponse;
 use lnurl::Tag;
-use log::error;
 use nostr::{Event, JsonUtil};
-use serde::{de, Deserialize, Deserializer, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{json, Value};
-use spark::services::InvoiceDescription;
 use std::fmt::Display;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LnurlCallbackParams {
-    pub amount: Option<u64>, // User specified amount in MilliSatoshi
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub amount: Option<CallbackAmount>, // User specified amount in MilliSatoshi, or `<amount>.<currency>` per LUD-21
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub comment: Option<String>, // Optional parameter to pass the LN WALLET user's comment to LN SERVICE
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub nostr: Option<String>, // Optional zap request
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub idempotency_key: Option<String>, // Optional client chosen key, callbacks only share an invoice if their keys match
+}
+
+/// Amount requested in a LNURL-pay callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackAmount {
+    Msats(u64),
+    /// An amount in the currency's smallest unit, e.g. `500.USD` for $5
+    Fiat {
+        amount: u64,
+        currency: String,
+    },
+}
+
+impl FromStr for CallbackAmount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('.') {
+            None => Ok(Self::Msats(s.parse()?)),
+            Some((amount, currency)) => Ok(Self::Fiat {
+                amount: amount.parse()?,
+                currency: currency.to_uppercase(),
+            }),
+        }
+    }
+}
+
+impl Display for CallbackAmount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Msats(msats) => write!(f, "{msats}"),
+            Self::Fiat { amount, currency } => write!(f, "{amount}.{currency}"),
+        }
+    }
+}
+
+impl Serialize for CallbackAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Converts `amount` smallest units of one of our advertised currencies to millisatoshis.
+async fn convert_fiat(
+    state: &State,
+    currency: &str,
+    amount: u64,
+) -> anyhow::Result<(u64, FiatQuote)> {
+    let currency = state
+        .currencies
+        .iter()
+        .find(|c| c.code.eq_ignore_ascii_case(currency))
+        .ok_or(ApiError::UnsupportedCurrency)?;
+    let btc_price = state
+        .prices
+        .btc_price(&currency.code)
+        .await
+        .map_err(ApiError::PriceUnavailable)?;
+
+    Ok((
+        currency.to_msats(amount, btc_price),
+        FiatQuote {
+            currency: currency.code.clone(),
+            amount,
+            btc_price,
+        },
+    ))
+}
+
+/// Price of a pay link in millisatoshis, converted at the current rate for fiat priced links.
+async fn pay_link_msats(state: &State, link: &PayLink) -> anyhow::Result<(u64, Option<FiatQuote>)> {
+    match (link.fiat_currency.as_deref(), link.fiat_amount) {
+        (Some(currency), Some(amount)) => {
+            let (msats, quote) = convert_fiat(state, currency, amount as u64).await?;
+            Ok((msats, Some(quote)))
+        }
+        _ => Ok((link.amount_msats as u64, None)),
+    }
+}
+
+/// Converts the requested amount to millisatoshis, checking it against the pay link's price if any.
+async fn resolve_amount(
+    state: &State,
+    amount: CallbackAmount,
+    link: Option<&PayLink>,
+) -> anyhow::Result<(u64, Option<FiatQuote>)> {
+    let (amount_msats, quote) = match amount {
+        CallbackAmount::Msats(msats) => (msats, None),
+        CallbackAmount::Fiat { amount, currency } => {
+            let (msats, quote) = convert_fiat(state, &currency, amount).await?;
+            (msats, Some(quote))
+        }
+    };
+
+    let Some(link) = link else {
+        return Ok((amount_msats, quote));
+    };
+
+    let (expected, link_quote) = pay_link_msats(state, link).await?;
+    // fiat prices may have moved since the wallet fetched the amount, allow 1% of slippage
+    let tolerance = if link_quote.is_some() {
+        expected / 100
+    } else {
+        0
+    };
+    if amount_msats.abs_diff(expected) > tolerance {
+        return Err(ApiError::AmountOutOfBounds.into());
+    }
+
+    Ok((amount_msats, link_quote.or(quote)))
+}
+
+/// Path of a lightning address, optionally pointing at one of the user's pay links.
+#[derive(Debug, Deserialize)]
+pub struct PayPath {
+    pub name: String,
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+/// Looks up an enabled pay link of the given user.
+async fn find_pay_link(state: &State, user_id: i32, slug: &str) -> anyhow::Result<PayLink> {
+    state
+        .storage
+        .get_pay_link_by_slug(user_id, slug)
+        .await?
+        .filter(|l| !l.disabled)
+        .ok_or(ApiError::PayLinkNotFound.into())
 }
 
 /// Creates a Lightning invoice and optionally stores zap request information.
@@ -36,91 +174,302 @@ pub struct LnurlCallbackParams {
 ///
 /// # Parameters
 /// * `state` - Application state containing LND client and configuration
-/// * `hash` - A description hash or identifier for the invoice
-/// * `amount_msats` - The invoice amount in millisatoshis
-/// * `zap_request` - Optional Nostr zap request event
+/// * `domain` - The domain the lightning address lives under
+/// * `name` - The username portion of the lightning address
+/// * `link` - Slug of the pay link being paid, if any
+/// * `params` - Callback parameters with the amount, comment and optional zap request
 ///
 /// # Returns
 /// A BOLT11 invoice if successful, or an error
 pub(crate) async fn get_invoice_impl(
     state: &State,
+    domain: &DomainSettings,
     name: &str,
+    link: Option<&str>,
     params: LnurlCallbackParams,
-) -> anyhow::Result<Bolt11Invoice> {
-    if params.amount.is_none() {
-        return Err(anyhow!("Missing amount parameter"));
-    }
-    let amount_msats = params.amount.unwrap();
-    if amount_msats < state.min_sendable || amount_msats > state.max_sendable {
-        return Err(anyhow!("Amount out of bounds"));
+) -> anyhow::Result<(Option<Bolt11Invoice>, Option<String>)> {
+    let amount = params.amount.ok_or(ApiError::MissingAmount)?;
+
+    if params
+        .comment
+        .as_ref()
+        .is_some_and(|c| c.chars().count() > domain.comment_allowed as usize)
+    {
+        return Err(ApiError::CommentTooLong.into());
     }
 
-    let mut conn = state.db_pool.get()?;
+    let user = state
+        .storage
+        .get_user_by_name(domain.id, name)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    logging::record_user(&user.name);
 
-    let user = User::get_by_name(&mut conn, name)?.ok_or(anyhow!("User not found"))?;
+    if user.disabled {
+        return Err(ApiError::UserDisabled.into());
+    }
 
     if user.disabled_zaps {
-        return Err(anyhow!("Zaps are disabled for this user"));
+        return Err(ApiError::ZapsDisabled.into());
+    }
+
+    let link = match link {
+        Some(slug) => Some(find_pay_link(state, user.id, slug).await?),
+        None => None,
+    };
+    let reuse_invoices = state.settings.read().unwrap().invoice_reuse_secs > 0;
+    let dedupe_key =
+        callback_dedupe_key(&amount, link.as_ref(), &params).filter(|_| reuse_invoices);
+
+    let (amount_msats, fiat) = resolve_amount(state, amount, link.as_ref()).await?;
+    if amount_msats < domain.min_sendable || amount_msats > max_sendable(state, domain) {
+        return Err(ApiError::AmountOutOfBounds.into());
+    }
+    // the wallet only creates invoices for whole sats, fiat amounts are rounded to them
+    if amount_msats % 1_000 != 0 {
+        return Err(
+            ApiError::InvalidRequest("Amount must be a whole number of sats".to_string()).into(),
+        );
+    }
+
+    // too large for lightning, only the on-chain fallback is offered. Zaps need an invoice
+    // for their receipt so they stay within max_sendable.
+    if amount_msats > domain.max_sendable {
+        if params.nostr.is_some() {
+            return Err(ApiError::AmountOutOfBounds.into());
+        }
+        let onchain = onchain_fallback(state, &user, amount_msats, None)
+            .await?
+            .ok_or(ApiError::AmountOutOfBounds)?;
+        return Ok((None, Some(onchain)));
     }
 
     let mut zap_request = None;
     let desc_hash = match params.nostr.as_ref() {
         None => {
-            let metadata = calc_metadata(name, &state.domain);
+            let metadata = match link.as_ref() {
+                Some(l) => calc_link_metadata(name, &domain.domain, &l.description),
+                None => calc_metadata(name, &domain.domain),
+            };
             sha256::Hash::hash(metadata.as_bytes())
         }
         Some(str) => {
-            let event = Event::from_json(str).map_err(|_| anyhow!("Invalid zap request"))?;
+            let event = Event::from_json(str).map_err(|_| ApiError::InvalidZapRequest)?;
             if event.kind != nostr::Kind::ZapRequest {
-                return Err(anyhow!("Invalid zap request"));
+                return Err(ApiError::InvalidZapRequest.into());
             }
             zap_request = Some(event);
             sha256::Hash::hash(str.as_bytes())
         }
     };
 
+    if let Some(key) = dedupe_key.as_deref() {
+        if let Some(invoice) = reusable_invoice(state, user.id, key).await? {
+            return Ok(reuse_invoice(state, &user, invoice).await);
+        }
+    }
+
+    let timer = state.metrics.create_invoice_seconds.start_timer();
     let resp = state
         .wallet
-        .create_lightning_invoice(
-            amount_msats / 1_000, // todo they dont support msats
-            Some(InvoiceDescription::DescriptionHash(
-                desc_hash.to_byte_array(),
-            )),
-            Some(user.pubkey()),
-        )
-        .await?;
-
-    let invoice = Bolt11Invoice::from_str(&resp.invoice)?;
+        .create_invoice(amount_msats, desc_hash, user.pubkey())
+        .await;
+    timer.observe_duration();
+    let resp = resp?;
+
+    let invoice = resp.bolt11;
+    logging::record_payment_hash(invoice.payment_hash());
     if invoice.amount_milli_satoshis().is_none()
         || invoice.amount_milli_satoshis().unwrap() != amount_msats
     {
-        return Err(anyhow!("Invoice amount mismatch"));
+        return Err(BackendError("Invoice amount mismatch".to_string()).into());
     }
 
-    conn.transaction::<_, anyhow::Error, _>(|conn| {
-        let invoice = NewInvoice {
-            user_id: user.id,
-            bolt11: resp.invoice,
-            amount_msats: amount_msats as i64,
-            preimage: resp.payment_preimage.unwrap_or_default(),
-            lnurlp_comment: params.comment,
-            state: InvoiceState::Pending as i32,
+    let new_invoice = NewInvoice {
+        user_id: user.id,
+        bolt11: invoice.to_string(),
+        amount_msats: amount_msats as i64,
+        preimage: resp.preimage.unwrap_or_default(),
+        lnurlp_comment: params.comment,
+        state: InvoiceState::Pending as i32,
+        receive_id: Some(resp.id),
+        pay_link_id: link.map(|l| l.id),
+        fiat_currency: fiat.as_ref().map(|f| f.currency.clone()),
+        fiat_amount: fiat.as_ref().map(|f| f.amount as i64),
+        fiat_rate: fiat.as_ref().map(|f| f.btc_price),
+        dedupe_key: dedupe_key.clone(),
+        payment_hash: Some(invoice.payment_hash().to_string()),
+    };
+    let res = state
+        .storage
+        .insert_invoice(new_invoice, zap_request.map(|z| z.as_json()))
+        .await;
+    let inserted = match (res, dedupe_key.as_deref()) {
+        (Ok(inserted), _) => inserted,
+        // a concurrent callback with the same key stored its invoice first, answer with that one
+        (Err(e), Some(key)) if is_unique_violation(&e) => {
+            let existing = state
+                .storage
+                .get_pending_invoice_by_dedupe_key(user.id, key)
+                .await?
+                .ok_or(e)?;
+            return Ok(reuse_invoice(state, &user, existing.bolt11()).await);
+        }
+        (Err(e), _) => return Err(e),
+    };
+    state.metrics.invoices_created.inc();
+    invoice_events::publish(state, &inserted, InvoiceState::Pending);
+
+    let onchain = onchain_option(state, &user, amount_msats, &invoice).await;
+    Ok((Some(invoice), onchain))
+}
+
+/// Identifies a callback by what ends up in the invoice, so retries of it can be answered
+/// with the same invoice. The amount is hashed as requested, fiat amounts map to the same
+/// key while the exchange rate moves.
+///
+/// Only callbacks carrying an `idempotencyKey` get a key, different payers sending the same
+/// amount must not be handed the same invoice.
+fn callback_dedupe_key(
+    amount: &CallbackAmount,
+    link: Option<&PayLink>,
+    params: &LnurlCallbackParams,
+) -> Option<String> {
+    let idempotency_key = params.idempotency_key.as_ref()?;
+    let preimage = json!([
+        amount.to_string(),
+        link.map(|l| l.id),
+        params.comment,
+        params.nostr,
+        idempotency_key,
+    ]);
+    Some(sha256::Hash::hash(preimage.to_string().as_bytes()).to_string())
+}
+
+/// The user's pending invoice for a callback with the same dedupe key, if it was created
+/// within the reuse window, hasn't expired and the wallet hasn't seen it paid.
+///
+/// An invoice that can't be reused gives up its key, only one pending invoice may hold it.
+async fn reusable_invoice(
+    state: &State,
+    user_id: i32,
+    dedupe_key: &str,
+) -> anyhow::Result<Option<Bolt11Invoice>> {
+    let Some(existing) = state
+        .storage
+        .get_pending_invoice_by_dedupe_key(user_id, dedupe_key)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let reuse_secs = state.settings.read().unwrap().invoice_reuse_secs;
+    let invoice = existing.bolt11();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let age = now.saturating_sub(invoice.duration_since_epoch());
+    let mut reusable = age <= Duration::from_secs(reuse_secs) && !invoice.is_expired();
+
+    // the invoice subscriber may not have picked up a payment yet
+    if reusable {
+        reusable = match existing.receive_id.as_deref() {
+            Some(id) => match state.wallet.payment_status(id).await {
+                Ok(status) => status == PaymentStatus::Pending,
+                Err(e) => {
+                    warn!("Error fetching payment status for {id}: {e}");
+                    false
+                }
+            },
+            None => false,
         };
-        let _inserted_invoice = invoice.insert(conn)?;
+    }
 
-        if let Some(zap_request) = zap_request {
-            let zap = Zap {
-                id: 0,
-                request: zap_request.as_json(),
-                event_id: None,
-            };
-            zap.insert(conn)?;
+    if !reusable {
+        state.storage.clear_invoice_dedupe_key(&existing).await?;
+        return Ok(None);
+    }
+
+    Ok(Some(invoice))
+}
+
+/// Answers a callback with an invoice created for an earlier one.
+async fn reuse_invoice(
+    state: &State,
+    user: &User,
+    invoice: Bolt11Invoice,
+) -> (Option<Bolt11Invoice>, Option<String>) {
+    state.metrics.invoices_reused.inc();
+    logging::record_payment_hash(invoice.payment_hash());
+    let amount_msats = invoice.amount_milli_satoshis().unwrap_or_default();
+    let onchain = onchain_option(state, user, amount_msats, &invoice).await;
+    (Some(invoice), onchain)
+}
+
+/// Largest amount `domain` accepts, amounts above its `max_sendable` up to
+/// `onchain_max_sendable` are only offered on-chain.
+fn max_sendable(state: &State, domain: &DomainSettings) -> u64 {
+    let settings = state.settings.read().unwrap();
+    match (settings.onchain_min_sendable, settings.onchain_max_sendable) {
+        (Some(_), Some(onchain_max)) => onchain_max.max(domain.max_sendable),
+        _ => domain.max_sendable,
+    }
+}
+
+/// The on-chain fallback for amounts of at least `onchain_min_sendable`, failing to
+/// create one doesn't fail the callback.
+async fn onchain_option(
+    state: &State,
+    user: &User,
+    amount_msats: u64,
+    invoice: &Bolt11Invoice,
+) -> Option<String> {
+    let onchain_min_sendable = state.settings.read().unwrap().onchain_min_sendable;
+    match onchain_min_sendable {
+        Some(min) if amount_msats >= min => {
+            onchain_fallback(state, user, amount_msats, Some(invoice))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to create on-chain fallback for {}: {e}", user.name);
+                    None
+                })
         }
+        _ => None,
+    }
+}
 
-        Ok(())
-    })?;
+/// BIP-21 URI paying `amount_msats` to the user's deposit address, with the invoice, if any,
+/// as the lightning option. The address is created on first use and kept, so deposits can be
+/// attributed to the user. `None` if the backend has no deposit addresses.
+async fn onchain_fallback(
+    state: &State,
+    user: &User,
+    amount_msats: u64,
+    invoice: Option<&Bolt11Invoice>,
+) -> anyhow::Result<Option<String>> {
+    let address = match user.deposit_address.clone() {
+        Some(address) => address,
+        None => match state.wallet.deposit_address(user.pubkey()).await {
+            Ok(address) => {
+                state
+                    .storage
+                    .set_user_deposit_address(user, &address)
+                    .await?;
+                address
+            }
+            Err(e) if e.is_unsupported() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        },
+    };
 
-    Ok(invoice)
+    // on-chain amounts can't go below a sat, round up so the payment isn't short
+    let amount = Amount::from_sat(amount_msats.div_ceil(1_000));
+    let mut uri = format!(
+        "bitcoin:{address}?amount={}",
+        amount.display_in(Denomination::Bitcoin)
+    );
+    if let Some(invoice) = invoice {
+        uri.push_str(&format!("&lightning={invoice}"));
+    }
+    Ok(Some(uri))
 }
 
 /// HTTP endpoint for generating Lightning invoices from a LNURL-pay request.
@@ -128,29 +477,47 @@ pub(crate) async fn get_invoice_impl(
 /// This route handles the callback phase of the LNURL-pay protocol.
 ///
 /// # Parameters
-/// * `hash` - Path parameter containing the description hash
+/// * `name` - Path parameter containing the username, optionally followed by a pay link slug
 /// * `params` - Query parameters including the amount and optional zap request
 /// * `state` - Application state
 ///
 /// # Returns
-/// A JSON response with the invoice and verification URL, or an error response
+/// A JSON response with the invoice and, for large amounts when enabled, a `bip21`
+/// on-chain fallback, or an error response. Amounts above `max_sendable` only get `bip21`.
 pub async fn get_invoice(
-    Path(name): Path<String>,
+    Path(PayPath { name, link }): Path<PayPath>,
     Query(params): Query<LnurlCallbackParams>,
+    host: Option<Host>,
     Extension(state): Extension<State>,
-) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    match get_invoice_impl(&state, &name, params).await {
-        Ok(invoice) => {
+) -> Result<Json<Value>, ApiError> {
+    let domain = resolve_domain(&state, host.as_ref().map(|h| h.0.as_str())).await?;
+
+    match get_invoice_impl(&state, &domain, &name, link.as_deref(), params).await {
+        Ok((invoice, onchain)) => {
             // let payment_hash = hex::encode(invoice.payment_hash().to_byte_array());
-            // let verify_url = format!("https://{}/verify/{name}/{payment_hash}", state.domain);
-            Ok(Json(json!({
+            // let verify_url = domain_url(&state, &domain, &format!("/verify/{name}/{payment_hash}"));
+            let mut resp = json!({
                 "status": "OK",
-                "pr": invoice,
                 // "verify": verify_url,
                 "routes": [],
-            })))
+            });
+            if let Some(invoice) = invoice {
+                resp["pr"] = json!(invoice);
+            }
+            if let Some(onchain) = onchain {
+                resp["bip21"] = json!(onchain);
+            }
+            Ok(Json(resp))
+        }
+        Err(e) => {
+            let e = ApiError::from(e);
+            state
+                .metrics
+                .invoice_failures
+                .with_label_values(&[e.code()])
+                .inc();
+            Err(e)
         }
-        Err(e) => Err(handle_anyhow_error(e)),
     }
 }
 
@@ -158,105 +525,271 @@ pub fn calc_metadata(name: &str, domain: &str) -> String {
     format!("[[\"text/identifier\",\"{name}@{domain}\"],[\"text/plain\",\"Sats for {name}\"]]",)
 }
 
+/// Metadata for a pay link, the description is user provided so it is escaped as JSON.
+pub fn calc_link_metadata(name: &str, domain: &str, description: &str) -> String {
+    json!([
+        ["text/identifier", format!("{name}@{domain}")],
+        ["text/plain", description],
+    ])
+    .to_string()
+}
+
 /// HTTP endpoint that provides the LNURL-pay metadata and parameters.
 ///
 /// This is the entry point for the LNURL-pay protocol, served at the .well-known/lnurlp/{name} path.
 ///
 /// # Parameters
 /// * `name` - Path parameter containing the username portion of the Lightning address
+/// * `link` - Optional path parameter with the slug of a fixed-amount pay link
+/// * `host` - The domain the request was made to, selects which domain's settings apply
 /// * `state` - Application state with domain and configuration
 ///
 /// # Returns
 /// A LNURL PayResponse with callback URL and other parameters, or an error response
 pub async fn get_lnurl_pay(
-    Path(name): Path<String>,
+    Path(PayPath { name, link }): Path<PayPath>,
+    host: Option<Host>,
     Extension(state): Extension<State>,
-) -> Result<Json<PayResponse>, (StatusCode, Json<Value>)> {
+) -> Result<Json<LnurlPayResponse>, ApiError> {
     if name.is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "status": "ERROR",
-                "reason": "Name parameter is required",
-            })),
+        return Err(ApiError::InvalidRequest(
+            "Name parameter is required".to_string(),
         ));
     }
+    logging::record_user(&name);
+
+    state.metrics.lnurl_lookups.inc();
 
-    let metadata = calc_metadata(&name, &state.domain);
+    let domain = resolve_domain(&state, host.as_ref().map(|h| h.0.as_str())).await?;
 
-    let callback = format!("https://{}/get-invoice/{name}", state.domain);
+    // only the plain address is served with the user's spark address,
+    // pay links are fixed-amount
+    let user = match link {
+        None => state.storage.get_user_by_name(domain.id, &name).await?,
+        Some(_) => None,
+    };
+    let spark_address = match user.as_ref() {
+        Some(u) => match state.wallet.spark_address(u.pubkey()).await {
+            Ok(address) => Some(address),
+            Err(e) if e.is_unsupported() => None,
+            Err(e) => {
+                warn!("Failed to get spark address for {}: {e}", u.name);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let (metadata, callback, min_sendable, max_sendable) = match link {
+        None => (
+            calc_metadata(&name, &domain.domain),
+            domain_url(&state, &domain, &format!("/get-invoice/{name}")),
+            domain.min_sendable,
+            max_sendable(&state, &domain),
+        ),
+        Some(slug) => {
+            let (link, amount_msats) = async {
+                let user = state
+                    .storage
+                    .get_user_by_name(domain.id, &name)
+                    .await?
+                    .filter(|u| !u.disabled)
+                    .ok_or(ApiError::UserNotFound)?;
+                let link = find_pay_link(&state, user.id, &slug).await?;
+                let (amount_msats, _) = pay_link_msats(&state, &link).await?;
+                Ok::<_, anyhow::Error>((link, amount_msats))
+            }
+            .await?;
+
+            (
+                calc_link_metadata(&name, &domain.domain, &link.description),
+                domain_url(&state, &domain, &format!("/get-invoice/{name}/{slug}")),
+                amount_msats,
+                amount_msats,
+            )
+        }
+    };
 
-    let resp = PayResponse {
+    let pay = PayResponse {
         callback,
-        min_sendable: state.min_sendable,
-        max_sendable: state.max_sendable,
+        min_sendable,
+        max_sendable,
         tag: Tag::PayRequest,
         metadata,
-        comment_allowed: Some(100),
-        allows_nostr: Some(true),
-        nostr_pubkey: Some(
-            state
-                .keys
-                .public_key()
-                .xonly()
-                .expect("cant get xonly pubkey"),
-        ),
+        comment_allowed: (domain.comment_allowed > 0).then_some(domain.comment_allowed),
+        // zaps are only advertised once we know who signs their receipts
+        allows_nostr: state.nostr_pubkey.get().map(|_| true),
+        nostr_pubkey: state
+            .nostr_pubkey
+            .get()
+            .map(|pk| pk.xonly().expect("cant get xonly pubkey")),
     };
 
-    Ok(Json(resp))
+    Ok(Json(LnurlPayResponse {
+        pay,
+        currencies: advertised_currencies(&state).await,
+        spark_address,
+    }))
+}
+
+/// [`PayResponse`] extended with the LUD-21 `currencies` payers may denominate amounts in,
+/// and the user's Spark address.
+#[derive(Serialize)]
+pub struct LnurlPayResponse {
+    #[serde(flatten)]
+    pub pay: PayResponse,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub currencies: Vec<Currency>,
+    /// The user's Spark address, Spark wallets can pay it directly without going over lightning
+    #[serde(rename = "sparkAddress", skip_serializing_if = "Option::is_none")]
+    pub spark_address: Option<String>,
+}
+
+/// Our currencies with their current multiplier, skipping any we can't get a price for.
+async fn advertised_currencies(state: &State) -> Vec<Currency> {
+    let mut currencies = Vec::with_capacity(state.currencies.len());
+    for currency in state.currencies.iter() {
+        match state.prices.btc_price(&currency.code).await {
+            Ok(price) => currencies.push(Currency {
+                multiplier: Some(currency.msats_per_unit(price)),
+                ..currency.clone()
+            }),
+            Err(e) => warn!("Failed to get {} price: {e}", currency.code),
+        }
+    }
+    currencies
+}
+
+/// Bech32 encodes the `.well-known/lnurlp` url of `name`, `None` if there is no such active user.
+pub(crate) async fn encode_lnurl(
+    state: &State,
+    domain: &DomainSettings,
+    name: &str,
+) -> anyhow::Result<Option<LnurlResponse>> {
+    match state.storage.get_user_by_name(domain.id, name).await? {
+        Some(user) if !user.disabled => (),
+        _ => return Ok(None),
+    }
+
+    let url = domain_url(state, domain, &format!("/.well-known/lnurlp/{name}"));
+    let lnurl = LnUrl::from_url(url.clone()).encode();
+
+    Ok(Some(LnurlResponse {
+        lnurl,
+        url,
+        address: format!("{name}@{}", domain.domain),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct LnurlResponse {
+    pub lnurl: String,
+    pub url: String,
+    pub address: String,
+}
+
+/// HTTP endpoint returning the `LNURL1...` form of a lightning address,
+/// for wallets that don't support lightning addresses.
+pub async fn get_lnurl(
+    Path(name): Path<String>,
+    host: Option<Host>,
+    Extension(state): Extension<State>,
+) -> Result<Json<LnurlResponse>, ApiError> {
+    let domain = resolve_domain(&state, host.as_ref().map(|h| h.0.as_str())).await?;
+
+    encode_lnurl(&state, &domain, &name)
+        .await?
+        .map(Json)
+        .ok_or(ApiError::UserNotFound)
 }
 
 #[derive(Deserialize, Clone)]
 pub struct RegisterRequest {
     pub name: String,
     pub pubkey: PublicKey,
+    /// Domain to register the name under, defaults to the one the request was made to
+    #[serde(default)]
+    pub domain: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct RegisterResponse {
     pub name: String,
+    pub domain: String,
 }
 
 pub async fn register(
     state: &State,
-    req: RegisterRequest,
-) -> Result<RegisterResponse, (StatusCode, String)> {
-    let mut conn = state.db_pool.get().map_err(|e| {
-        error!("DB connection error: {e}");
-        (StatusCode::INTERNAL_SERVER_ERROR, "ServerError".to_string())
-    })?;
+    host: Option<&str>,
+    mut req: RegisterRequest,
+) -> Result<RegisterResponse, ApiError> {
+    // names are looked up case-insensitively, store them in one canonical form
+    req.name = req.name.to_lowercase();
+    logging::record_user(&req.name);
+    let domain = match req.domain.as_deref() {
+        Some(domain) => lookup_domain(state, domain)
+            .await?
+            .ok_or(ApiError::DomainNotFound)?,
+        None => resolve_domain(state, host).await?,
+    };
+
+    let reserved = state
+        .settings
+        .read()
+        .unwrap()
+        .reserved_names
+        .contains(&req.name);
+    // check if the user provided name has been banned by an admin
+    if reserved || state.storage.is_name_banned(&req.name).await? {
+        return Err(ApiError::NameBanned);
+    }
 
     // check if the user provided name is taken
-    match User::get_by_name(&mut conn, &req.name) {
-        Ok(Some(_)) => {
-            return Err((StatusCode::BAD_REQUEST, "NameTaken".to_string()));
-        }
-        Ok(None) => (),
-        Err(e) => {
-            error!("Error checking name availability: {e:?}");
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, "ServerError".to_string()));
-        }
+    if state
+        .storage
+        .get_user_by_name(domain.id, &req.name)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::NameTaken);
     }
 
+    let name = req.name.clone();
     let new_user = NewUser {
         pubkey: req.pubkey.to_string(),
         name: req.name,
+        domain_id: domain.id,
     };
-    match new_user.insert(&mut conn) {
-        Ok(u) => Ok(RegisterResponse { name: u.name }),
-        Err(e) => {
-            error!("Error inserting new user: {e:?}");
-            Err((StatusCode::INTERNAL_SERVER_ERROR, "ServerError".to_string()))
+    let u = match state.storage.insert_user(new_user).await {
+        Ok(u) => u,
+        // a concurrent registration got the name, or the pubkey already has a name here
+        Err(e) if is_unique_violation(&e) => {
+            return Err(
+                match state.storage.get_user_by_name(domain.id, &name).await? {
+                    Some(_) => ApiError::NameTaken,
+                    None => ApiError::PubkeyTaken,
+                },
+            )
         }
-    }
+        Err(e) => return Err(e.into()),
+    };
+    state.metrics.registrations.inc();
+    Ok(RegisterResponse {
+        name: u.name,
+        domain: domain.domain,
+    })
 }
 
 pub async fn register_route(
+    host: Option<Host>,
     Extension(state): Extension<State>,
     Json(req): Json<RegisterRequest>,
-) -> Result<Json<RegisterResponse>, (StatusCode, String)> {
-    let res = register(&state, req).await?;
-    Ok(Json(res))
+) -> Result<Json<RegisterResponse>, ApiError> {
+    let res = register(&state, host.as_ref().map(|h| h.0.as_str()), req).await;
+    if let Err(e) = res.as_ref() {
+        warn!(code = e.code(), "Registration failed");
+    }
+    Ok(Json(res?))
 }
 
 /// HTTP endpoint for verifying the status of a Lightning invoice payment.
@@ -272,15 +805,9 @@ pub async fn register_route(
 pub async fn verify(
     Path((_desc_hash, _pay_hash)): Path<(String, String)>,
     Extension(_state): Extension<State>,
-) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+) -> Result<Json<Value>, ApiError> {
     // todo implement
-    Err((
-        StatusCode::BAD_REQUEST,
-        Json(json!({
-            "status": "ERROR",
-            "reason": "Invalid payment hash",
-        })),
-    ))
+    Err(ApiError::InvalidRequest("Invalid payment hash".to_string()))
 
     // let mut lnd = state.lnd.clone();
     //
@@ -362,21 +889,6 @@ pub async fn verify(
     // }
 }
 
-/// Utility function for converting anyhow errors to HTTP response format.
-///
-/// # Parameters
-/// * `err` - The anyhow Error to convert
-///
-/// # Returns
-/// A tuple containing a 400 Bad Request status code and a JSON error response
-pub(crate) fn handle_anyhow_error(err: anyhow::Error) -> (StatusCode, Json<Value>) {
-    let err = json!({
-        "status": "ERROR",
-        "reason": format!("{err}"),
-    });
-    (StatusCode::BAD_REQUEST, Json(err))
-}
-
 /// Fallback route handler that returns a 404 Not Found response
 /// when a request is made to a non-existent route.
 ///
@@ -384,31 +896,10 @@ pub(crate) fn handle_anyhow_error(err: anyhow::Error) -> (StatusCode, Json<Value
 /// * `uri` - The URI of the request
 ///
 /// # Returns
-/// A 404 status code and a message indicating the route was not found
-pub async fn fallback(uri: Uri) -> (StatusCode, String) {
-    (StatusCode::NOT_FOUND, format!("No route for {}", uri))
-}
-
-#[derive(Serialize)]
-pub struct HealthResponse {
-    pub status: String,
-    pub version: String,
-}
-
-impl HealthResponse {
-    /// Fabricate a status: pass response without checking database connectivity
-    pub fn new_ok() -> Self {
-        Self {
-            status: String::from("pass"),
-            version: String::from("0"),
-        }
-    }
-}
-
-/// IETF draft RFC for HTTP API Health Checks:
-/// https://datatracker.ietf.org/doc/html/draft-inadarei-api-health-check
-pub async fn health_check() -> Result<Json<HealthResponse>, (StatusCode, String)> {
-    Ok(Json(HealthResponse::new_ok()))
+/// A 404 `route_not_found` error
+pub async fn fallback(uri: Uri) -> ApiError {
+    debug!("No route for {uri}");
+    ApiError::RouteNotFound
 }
 
 pub fn empty_string_as_none<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
@@ -423,3 +914,47 @@ where
         Some(s) => FromStr::from_str(s).map_err(de::Error::custom).map(Some),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_metadata_escapes_the_description() {
+        let metadata = calc_link_metadata("alice", "example.com", "A \"large\" coffee");
+        let parsed: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(
+            parsed,
+            json!([
+                ["text/identifier", "alice@example.com"],
+                ["text/plain", "A \"large\" coffee"],
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_callback_amount() {
+        assert_eq!(
+            CallbackAmount::from_str("5000").unwrap(),
+            CallbackAmount::Msats(5_000)
+        );
+        assert_eq!(
+            CallbackAmount::from_str("500.usd").unwrap(),
+            CallbackAmount::Fiat {
+                amount: 500,
+                currency: "USD".to_string()
+            }
+        );
+        assert!(CallbackAmount::from_str("").is_err());
+        assert!(CallbackAmount::from_str("abc").is_err());
+        assert!(CallbackAmount::from_str("-1").is_err());
+        assert!(CallbackAmount::from_str("x.USD").is_err());
+    }
+
+    #[test]
+    fn callback_amount_round_trips() {
+        for s in ["5000", "500.USD"] {
+            assert_eq!(CallbackAmount::from_str(s).unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::anyhow;
+use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr::{FromBech32, Keys, SecretKey, ToBech32};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+/// Scrypt work factor for seed files we encrypt, the NIP-49 recommended minimum.
+const ENCRYPT_LOG_N: u8 = 16;
+
+/// Reads a wallet seed file, which holds one of:
+///
+/// * a BIP-39 mnemonic
+/// * a hex encoded seed
+/// * a NIP-49 `ncryptsec` encrypted 32 byte seed, decrypted with `password`. NIP-49 only
+///   holds 32 bytes, so these come from [`export_legacy_seed`], mnemonics stay plain text
+pub fn read_seed_file(path: &Path, password: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read seed file {}: {e}", path.display()))?;
+    parse_seed(contents.trim(), password)
+}
+
+pub fn parse_seed(seed: &str, password: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    if seed.starts_with("ncryptsec1") {
+        let password = password.ok_or(anyhow!("Seed is encrypted, --seed-password is required"))?;
+        let encrypted = EncryptedSecretKey::from_bech32(seed)?;
+        let key = encrypted
+            .decrypt(password)
+            .map_err(|_| anyhow!("Failed to decrypt seed, wrong password?"))?;
+        return Ok(key.as_secret_bytes().to_vec());
+    }
+
+    if seed.contains(' ') {
+        return mnemonic_seed(seed);
+    }
+
+    let bytes = hex::decode(seed).map_err(|_| anyhow!("Seed is not a mnemonic or hex"))?;
+    if bytes.len() != 32 && bytes.len() != 64 {
+        return Err(anyhow!("Hex seeds must be 32 or 64 bytes"));
+    }
+    Ok(bytes)
+}
+
+pub fn mnemonic_seed(mnemonic: &str) -> anyhow::Result<Vec<u8>> {
+    let mnemonic =
+        bip39::Mnemonic::parse(mnemonic).map_err(|e| anyhow!("Invalid mnemonic: {e}"))?;
+    Ok(mnemonic.to_seed("").to_vec())
+}
+
+/// Writes the seed older versions derived from the nostr key to `path`, so the wallet can
+/// be moved to its own seed before rotating the nostr key. Encrypted when `password` is given.
+pub fn export_legacy_seed(keys: &Keys, path: &Path, password: Option<&str>) -> anyhow::Result<()> {
+    let secret: &SecretKey = keys.secret_key();
+    let contents = match password {
+        Some(password) => {
+            EncryptedSecretKey::new(secret, password, ENCRYPT_LOG_N, KeySecurity::Medium)?
+                .to_bech32()?
+        }
+        None => hex::encode(secret.as_secret_bytes()),
+    };
+
+    // only readable by us, and never overwrite an existing seed
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => anyhow!("{} already exists", path.display()),
+            _ => anyhow!("Failed to create {}: {e}", path.display()),
+        })?;
+    file.write_all((contents + "\n").as_bytes())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn export_legacy_seed_file() {
+        let keys = Keys::generate();
+        let path = std::env::temp_dir().join(format!("lnurl-seed-{}", std::process::id()));
+
+        export_legacy_seed(&keys, &path, None).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let seed = read_seed_file(&path, None);
+        let again = export_legacy_seed(&keys, &path, None);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(seed.unwrap(), keys.secret_key().as_secret_bytes());
+        assert!(again.unwrap_err().to_string().contains("already exists"));
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::anyhow;
+use nostr::signer::NostrSigner;
+use nostr::{Event, EventBuilder, Keys, PublicKey, ToBech32};
+use nostr_connect::client::NostrConnect;
+use nostr_connect::prelude::NostrConnectURI;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long to wait before asking an unresponsive remote signer for its pubkey again.
+const PUBLIC_KEY_RETRY: Duration = Duration::from_secs(30);
+
+/// Signs zap receipts, with the local nsec or through a NIP-46 remote signer.
+#[derive(Clone)]
+pub enum ZapSigner {
+    Keys(Keys),
+    Remote(Arc<NostrConnect>),
+}
+
+impl ZapSigner {
+    /// Connects to the remote signer at a `bunker://` url as `client_keys`,
+    /// requests to it fail after `timeout`.
+    pub fn remote(bunker_url: &str, client_keys: Keys, timeout: Duration) -> anyhow::Result<Self> {
+        let uri = NostrConnectURI::parse(bunker_url)?;
+        let signer = NostrConnect::new(uri, client_keys, timeout, None)?;
+        Ok(Self::Remote(Arc::new(signer)))
+    }
+
+    pub async fn public_key(&self) -> anyhow::Result<PublicKey> {
+        match self {
+            Self::Keys(keys) => Ok(keys.public_key()),
+            Self::Remote(signer) => Ok(signer.get_public_key().await?),
+        }
+    }
+
+    pub async fn sign(&self, builder: EventBuilder) -> anyhow::Result<Event> {
+        match self {
+            Self::Keys(keys) => Ok(builder.sign_with_keys(keys)?),
+            Self::Remote(signer) => Ok(builder.sign(signer.as_ref()).await?),
+        }
+    }
+}
+
+/// Asks the signer for its pubkey until it answers, so the server can start while a
+/// remote signer is offline. Zaps aren't advertised until `pubkey` is set.
+pub async fn start_public_key_fetch(signer: ZapSigner, pubkey: Arc<OnceLock<PublicKey>>) {
+    loop {
+        match signer.public_key().await {
+            Ok(pk) => {
+                info!("Zap receipts are signed by {pk}");
+                let _ = pubkey.set(pk);
+                return;
+            }
+            Err(e) => warn!(
+                "Failed to get the zap signer's pubkey, retrying in {PUBLIC_KEY_RETRY:?}: {e}"
+            ),
+        }
+        tokio::time::sleep(PUBLIC_KEY_RETRY).await;
+    }
+}
+
+/// The key we connect to the remote signer as, read from `path` or generated and saved there
+/// on first use. Bunkers that pin their clients only accept the key they first saw.
+pub fn client_keys(path: &Path) -> anyhow::Result<Keys> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Keys::parse(contents.trim())
+            .map_err(|e| anyhow!("Invalid signer client key in {}: {e}", path.display())),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            let keys = Keys::generate();
+            // only readable by us, and never overwrite a key a concurrent start saved
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .map_err(|e| anyhow!("Failed to create {}: {e}", path.display()))?;
+            file.write_all((keys.secret_key().to_bech32()? + "\n").as_bytes())?;
+            info!("Saved new signer client key to {}", path.display());
+            Ok(keys)
+        }
+        Err(e) => Err(anyhow!("Failed to read {}: {e}", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn client_keys_are_kept() {
+        let path = std::env::temp_dir().join(format!("lnurl-bunker-key-{}", std::process::id()));
+
+        let first = client_keys(&path);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let second = client_keys(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(first.unwrap().public_key(), second.unwrap().public_key());
+    }
+}
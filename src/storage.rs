@@ -0,0 +1,836 @@
+use crate::models::banned_name::BannedName;
+use crate::models::deposit::{Deposit, NewDeposit};
+use crate::models::domain::{Domain, NewDomain};
+use crate::models::invoice::{Invoice, NewInvoice};
+use crate::models::pay_link::{NewPayLink, PayLink};
+use crate::models::user::{NewUser, User, UserFilter};
+use crate::models::zap::Zap;
+use crate::models::{db_run, DbConnection};
+use anyhow::anyhow;
+use diesel::backend::Backend;
+use diesel::connection::SimpleConnection;
+use diesel::migration::MigrationSource;
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool};
+use diesel::result::DatabaseErrorKind;
+use diesel::{PgConnection, RunQueryDsl, SqliteConnection};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use prometheus::Histogram;
+use std::collections::HashSet;
+
+pub const PG_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations_sqlite");
+
+/// Persistence for users, invoices and zaps.
+///
+/// Handlers only go through this trait so the database can be swapped out,
+/// see [`DieselStorage`] for the Postgres and SQLite implementation.
+/// Methods are async so implementations never block the runtime's worker threads.
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    /// Runs a trivial query to check the database is reachable.
+    async fn ping(&self) -> anyhow::Result<()>;
+
+    async fn get_users(&self) -> anyhow::Result<Vec<User>>;
+    async fn search_users(&self, filter: UserFilter) -> anyhow::Result<Vec<User>>;
+    async fn get_users_with_deposit_address(&self) -> anyhow::Result<Vec<User>>;
+    /// Number of users and how many of them are disabled.
+    async fn count_users(&self) -> anyhow::Result<(i64, i64)>;
+    async fn count_domain_users(&self, domain: &Domain) -> anyhow::Result<i64>;
+    /// Every user named `name` across all domains, ignoring case.
+    async fn get_users_by_name(&self, name: &str) -> anyhow::Result<Vec<User>>;
+    /// Looks up `name` within a domain ignoring case, `None` being the primary domain.
+    async fn get_user_by_name(
+        &self,
+        domain_id: Option<i32>,
+        name: &str,
+    ) -> anyhow::Result<Option<User>>;
+    async fn insert_user(&self, user: NewUser) -> anyhow::Result<User>;
+    async fn set_user_disabled(&self, user: &User, disabled: bool) -> anyhow::Result<()>;
+    async fn set_user_deposit_address(&self, user: &User, address: &str) -> anyhow::Result<()>;
+    /// Deletes the user, freeing up the name. Their invoices, zaps, pay links and deposits are kept.
+    async fn delete_user(&self, user: &User) -> anyhow::Result<()>;
+
+    async fn get_domains(&self) -> anyhow::Result<Vec<Domain>>;
+    async fn get_domain(&self, domain: &str) -> anyhow::Result<Option<Domain>>;
+    async fn insert_domain(&self, domain: NewDomain) -> anyhow::Result<Domain>;
+    async fn delete_domain(&self, domain: &Domain) -> anyhow::Result<()>;
+
+    async fn get_banned_names(&self) -> anyhow::Result<Vec<BannedName>>;
+    async fn is_name_banned(&self, name: &str) -> anyhow::Result<bool>;
+    async fn ban_name(&self, name: &str) -> anyhow::Result<()>;
+    async fn unban_name(&self, name: &str) -> anyhow::Result<()>;
+
+    async fn get_pay_links(&self, user_id: i32) -> anyhow::Result<Vec<PayLink>>;
+    async fn get_pay_link(&self, id: i32) -> anyhow::Result<Option<PayLink>>;
+    async fn get_pay_link_by_slug(
+        &self,
+        user_id: i32,
+        slug: &str,
+    ) -> anyhow::Result<Option<PayLink>>;
+    async fn insert_pay_link(&self, link: NewPayLink) -> anyhow::Result<PayLink>;
+    async fn set_pay_link_disabled(&self, link: &PayLink, disabled: bool) -> anyhow::Result<()>;
+    /// Number of settled invoices paid through the link and their total in msats.
+    async fn get_pay_link_totals(&self, link: &PayLink) -> anyhow::Result<(i64, i64)>;
+
+    async fn get_invoices(&self) -> anyhow::Result<Vec<Invoice>>;
+    /// Number of invoices in each state and their total amount in msats.
+    async fn get_invoice_totals(&self) -> anyhow::Result<Vec<(i32, i64, i64)>>;
+    async fn get_invoice(&self, id: i32) -> anyhow::Result<Option<Invoice>>;
+    async fn get_invoices_by_state(&self, state: i32) -> anyhow::Result<Vec<Invoice>>;
+    async fn get_invoice_by_payment_hash(
+        &self,
+        payment_hash: &str,
+    ) -> anyhow::Result<Option<Invoice>>;
+    async fn get_pending_invoice_by_dedupe_key(
+        &self,
+        user_id: i32,
+        dedupe_key: &str,
+    ) -> anyhow::Result<Option<Invoice>>;
+    /// Inserts the invoice and its zap request, if any, atomically.
+    async fn insert_invoice(
+        &self,
+        invoice: NewInvoice,
+        zap_request: Option<String>,
+    ) -> anyhow::Result<Invoice>;
+    async fn set_invoice_state(&self, invoice: &Invoice, state: i32) -> anyhow::Result<()>;
+    async fn clear_invoice_dedupe_key(&self, invoice: &Invoice) -> anyhow::Result<()>;
+    async fn set_invoice_payment_hash(
+        &self,
+        invoice: &Invoice,
+        payment_hash: &str,
+    ) -> anyhow::Result<()>;
+
+    async fn get_deposits(&self) -> anyhow::Result<Vec<Deposit>>;
+    async fn get_deposit_by_outpoint(
+        &self,
+        txid: &str,
+        vout: i32,
+    ) -> anyhow::Result<Option<Deposit>>;
+    async fn insert_deposit(&self, deposit: NewDeposit) -> anyhow::Result<Deposit>;
+    async fn set_deposit_state(&self, deposit: &Deposit, state: i32) -> anyhow::Result<()>;
+
+    async fn get_zaps(&self) -> anyhow::Result<Vec<Zap>>;
+    async fn count_zaps(&self) -> anyhow::Result<i64>;
+    async fn get_zap(&self, invoice_id: i32) -> anyhow::Result<Option<Zap>>;
+    async fn set_zap_event_id(&self, zap: &Zap, event_id: String) -> anyhow::Result<()>;
+}
+
+/// Which database a [`DieselStorage`] is connected to.
+#[derive(Clone)]
+pub enum DbPool {
+    Postgres(Pool<ConnectionManager<PgConnection>>),
+    Sqlite(Pool<ConnectionManager<SqliteConnection>>),
+}
+
+/// Turns on foreign keys and waits on locks instead of failing, neither is the SQLite default.
+#[derive(Debug, Clone, Copy)]
+struct SqliteCustomizer;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqliteCustomizer {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+impl DbPool {
+    pub fn postgres(url: &str, max_size: u32) -> anyhow::Result<Self> {
+        let manager = ConnectionManager::<PgConnection>::new(url);
+        let pool = Pool::builder()
+            .max_size(max_size)
+            .test_on_check_out(true)
+            .build(manager)?;
+
+        Ok(Self::Postgres(pool))
+    }
+
+    pub fn sqlite(path: &str, max_size: u32) -> anyhow::Result<Self> {
+        let manager = ConnectionManager::<SqliteConnection>::new(path);
+        let pool = Pool::builder()
+            .max_size(max_size)
+            .connection_customizer(Box::new(SqliteCustomizer))
+            .test_on_check_out(true)
+            .build(manager)?;
+
+        Ok(Self::Sqlite(pool))
+    }
+
+    pub fn get(&self) -> anyhow::Result<DbConnection> {
+        Ok(match self {
+            DbPool::Postgres(pool) => DbConnection::Postgres(pool.get()?),
+            DbPool::Sqlite(pool) => DbConnection::Sqlite(pool.get()?),
+        })
+    }
+
+    /// Runs the embedded migrations that haven't been applied yet, returning their versions.
+    pub fn run_migrations(&self) -> anyhow::Result<Vec<String>> {
+        let versions = match self.get()? {
+            DbConnection::Postgres(mut conn) => conn
+                .run_pending_migrations(PG_MIGRATIONS)
+                .map_err(|e| anyhow!("Failed to run migrations: {e}"))?,
+            DbConnection::Sqlite(mut conn) => conn
+                .run_pending_migrations(SQLITE_MIGRATIONS)
+                .map_err(|e| anyhow!("Failed to run migrations: {e}"))?,
+        };
+
+        Ok(versions.into_iter().map(|v| v.to_string()).collect())
+    }
+
+    /// Errors if the database has migrations applied that this binary doesn't know about,
+    /// meaning it was migrated by a newer version and our queries may no longer match.
+    pub fn check_schema_version(&self) -> anyhow::Result<()> {
+        let unknown = match self.get()? {
+            DbConnection::Postgres(mut conn) => unknown_migrations(&mut *conn, PG_MIGRATIONS)?,
+            DbConnection::Sqlite(mut conn) => unknown_migrations(&mut *conn, SQLITE_MIGRATIONS)?,
+        };
+
+        if !unknown.is_empty() {
+            return Err(anyhow!(
+                "Database schema is newer than this binary, unknown migrations: {}",
+                unknown.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `err` comes from an insert or update that hit a unique index, e.g. when a
+/// concurrent request inserted the same row first.
+pub fn is_unique_violation(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<diesel::result::Error>(),
+        Some(diesel::result::Error::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            _
+        ))
+    )
+}
+
+fn unknown_migrations<DB: Backend>(
+    harness: &mut impl MigrationHarness<DB>,
+    source: EmbeddedMigrations,
+) -> anyhow::Result<Vec<String>> {
+    let known: HashSet<String> = MigrationSource::<DB>::migrations(&source)
+        .map_err(|e| anyhow!("Failed to load migrations: {e}"))?
+        .iter()
+        .map(|m| m.name().version().to_string())
+        .collect();
+
+    let applied = harness
+        .applied_migrations()
+        .map_err(|e| anyhow!("Failed to read applied migrations: {e}"))?;
+
+    Ok(applied
+        .into_iter()
+        .map(|v| v.to_string())
+        .filter(|v| !known.contains(v))
+        .collect())
+}
+
+/// [`Storage`] implemented with diesel, against either Postgres or SQLite.
+///
+/// Diesel is synchronous, so every call checks out a connection and runs its
+/// queries on tokio's blocking thread pool.
+pub struct DieselStorage {
+    pool: DbPool,
+    pool_wait: Histogram,
+}
+
+impl DieselStorage {
+    /// `pool_wait` records how long each call waited for a connection.
+    pub fn new(pool: DbPool, pool_wait: Histogram) -> Self {
+        Self { pool, pool_wait }
+    }
+
+    async fn run<T, F>(&self, f: F) -> anyhow::Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DbConnection) -> anyhow::Result<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let pool_wait = self.pool_wait.clone();
+        tokio::task::spawn_blocking(move || {
+            let timer = pool_wait.start_timer();
+            let mut conn = pool.get()?;
+            timer.observe_duration();
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for DieselStorage {
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.run(|conn| {
+            db_run!(conn, |conn| {
+                diesel::sql_query("SELECT 1").execute(conn)?;
+            });
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_users(&self) -> anyhow::Result<Vec<User>> {
+        self.run(User::get_users).await
+    }
+
+    async fn get_users_with_deposit_address(&self) -> anyhow::Result<Vec<User>> {
+        self.run(User::get_with_deposit_address).await
+    }
+
+    async fn search_users(&self, filter: UserFilter) -> anyhow::Result<Vec<User>> {
+        self.run(move |conn| User::search(conn, &filter)).await
+    }
+
+    async fn count_users(&self) -> anyhow::Result<(i64, i64)> {
+        self.run(User::count).await
+    }
+
+    async fn count_domain_users(&self, domain: &Domain) -> anyhow::Result<i64> {
+        let domain_id = domain.id;
+        self.run(move |conn| User::count_in_domain(conn, domain_id))
+            .await
+    }
+
+    async fn get_users_by_name(&self, name: &str) -> anyhow::Result<Vec<User>> {
+        let name = name.to_string();
+        self.run(move |conn| User::get_all_by_name(conn, &name))
+            .await
+    }
+
+    async fn get_user_by_name(
+        &self,
+        domain_id: Option<i32>,
+        name: &str,
+    ) -> anyhow::Result<Option<User>> {
+        let name = name.to_string();
+        self.run(move |conn| User::get_by_name(conn, domain_id, &name))
+            .await
+    }
+
+    async fn insert_user(&self, user: NewUser) -> anyhow::Result<User> {
+        self.run(move |conn| user.insert(conn)).await
+    }
+
+    async fn set_user_disabled(&self, user: &User, disabled: bool) -> anyhow::Result<()> {
+        let user = user.clone();
+        self.run(move |conn| user.set_disabled(conn, disabled))
+            .await
+    }
+
+    async fn set_user_deposit_address(&self, user: &User, address: &str) -> anyhow::Result<()> {
+        let user = user.clone();
+        let address = address.to_string();
+        self.run(move |conn| user.set_deposit_address(conn, &address))
+            .await
+    }
+
+    async fn delete_user(&self, user: &User) -> anyhow::Result<()> {
+        let user = user.clone();
+        self.run(move |conn| user.delete(conn)).await
+    }
+
+    async fn get_domains(&self) -> anyhow::Result<Vec<Domain>> {
+        self.run(Domain::get_domains).await
+    }
+
+    async fn get_domain(&self, domain: &str) -> anyhow::Result<Option<Domain>> {
+        let domain = domain.to_string();
+        self.run(move |conn| Domain::get_by_domain(conn, &domain))
+            .await
+    }
+
+    async fn insert_domain(&self, domain: NewDomain) -> anyhow::Result<Domain> {
+        self.run(move |conn| domain.insert(conn)).await
+    }
+
+    async fn delete_domain(&self, domain: &Domain) -> anyhow::Result<()> {
+        let domain = domain.clone();
+        self.run(move |conn| domain.delete(conn)).await
+    }
+
+    async fn get_banned_names(&self) -> anyhow::Result<Vec<BannedName>> {
+        self.run(BannedName::get_banned_names).await
+    }
+
+    async fn is_name_banned(&self, name: &str) -> anyhow::Result<bool> {
+        let name = name.to_string();
+        self.run(move |conn| BannedName::is_banned(conn, &name))
+            .await
+    }
+
+    async fn ban_name(&self, name: &str) -> anyhow::Result<()> {
+        let name = name.to_string();
+        self.run(move |conn| BannedName::ban(conn, &name)).await
+    }
+
+    async fn unban_name(&self, name: &str) -> anyhow::Result<()> {
+        let name = name.to_string();
+        self.run(move |conn| BannedName::unban(conn, &name)).await
+    }
+
+    async fn get_pay_links(&self, user_id: i32) -> anyhow::Result<Vec<PayLink>> {
+        self.run(move |conn| PayLink::get_by_user(conn, user_id))
+            .await
+    }
+
+    async fn get_pay_link(&self, id: i32) -> anyhow::Result<Option<PayLink>> {
+        self.run(move |conn| PayLink::get_by_id(conn, id)).await
+    }
+
+    async fn get_pay_link_by_slug(
+        &self,
+        user_id: i32,
+        slug: &str,
+    ) -> anyhow::Result<Option<PayLink>> {
+        let slug = slug.to_string();
+        self.run(move |conn| PayLink::get_by_slug(conn, user_id, &slug))
+            .await
+    }
+
+    async fn insert_pay_link(&self, link: NewPayLink) -> anyhow::Result<PayLink> {
+        self.run(move |conn| link.insert(conn)).await
+    }
+
+    async fn set_pay_link_disabled(&self, link: &PayLink, disabled: bool) -> anyhow::Result<()> {
+        let link = link.clone();
+        self.run(move |conn| link.set_disabled(conn, disabled))
+            .await
+    }
+
+    async fn get_pay_link_totals(&self, link: &PayLink) -> anyhow::Result<(i64, i64)> {
+        let link = link.clone();
+        self.run(move |conn| link.settled_totals(conn)).await
+    }
+
+    async fn get_invoices(&self) -> anyhow::Result<Vec<Invoice>> {
+        self.run(Invoice::get_invoices).await
+    }
+
+    async fn get_invoice_totals(&self) -> anyhow::Result<Vec<(i32, i64, i64)>> {
+        self.run(Invoice::totals_by_state).await
+    }
+
+    async fn get_invoice(&self, id: i32) -> anyhow::Result<Option<Invoice>> {
+        self.run(move |conn| Invoice::get_by_id(conn, id)).await
+    }
+
+    async fn get_invoices_by_state(&self, state: i32) -> anyhow::Result<Vec<Invoice>> {
+        self.run(move |conn| Invoice::get_by_state(conn, state))
+            .await
+    }
+
+    async fn get_invoice_by_payment_hash(
+        &self,
+        payment_hash: &str,
+    ) -> anyhow::Result<Option<Invoice>> {
+        let payment_hash = payment_hash.to_string();
+        self.run(move |conn| Invoice::get_by_payment_hash(conn, &payment_hash))
+            .await
+    }
+
+    async fn get_pending_invoice_by_dedupe_key(
+        &self,
+        user_id: i32,
+        dedupe_key: &str,
+    ) -> anyhow::Result<Option<Invoice>> {
+        let dedupe_key = dedupe_key.to_string();
+        self.run(move |conn| Invoice::get_pending_by_dedupe_key(conn, user_id, &dedupe_key))
+            .await
+    }
+
+    async fn insert_invoice(
+        &self,
+        invoice: NewInvoice,
+        zap_request: Option<String>,
+    ) -> anyhow::Result<Invoice> {
+        self.run(move |conn| invoice.insert_with_zap(conn, zap_request))
+            .await
+    }
+
+    async fn set_invoice_state(&self, invoice: &Invoice, state: i32) -> anyhow::Result<()> {
+        let invoice = invoice.clone();
+        self.run(move |conn| invoice.set_state(conn, state)).await
+    }
+
+    async fn clear_invoice_dedupe_key(&self, invoice: &Invoice) -> anyhow::Result<()> {
+        let invoice = invoice.clone();
+        self.run(move |conn| invoice.clear_dedupe_key(conn)).await
+    }
+
+    async fn set_invoice_payment_hash(
+        &self,
+        invoice: &Invoice,
+        payment_hash: &str,
+    ) -> anyhow::Result<()> {
+        let invoice = invoice.clone();
+        let payment_hash = payment_hash.to_string();
+        self.run(move |conn| invoice.set_payment_hash(conn, &payment_hash))
+            .await
+    }
+
+    async fn get_deposits(&self) -> anyhow::Result<Vec<Deposit>> {
+        self.run(Deposit::get_deposits).await
+    }
+
+    async fn get_deposit_by_outpoint(
+        &self,
+        txid: &str,
+        vout: i32,
+    ) -> anyhow::Result<Option<Deposit>> {
+        let txid = txid.to_string();
+        self.run(move |conn| Deposit::get_by_outpoint(conn, &txid, vout))
+            .await
+    }
+
+    async fn insert_deposit(&self, deposit: NewDeposit) -> anyhow::Result<Deposit> {
+        self.run(move |conn| deposit.insert(conn)).await
+    }
+
+    async fn set_deposit_state(&self, deposit: &Deposit, state: i32) -> anyhow::Result<()> {
+        let deposit = deposit.clone();
+        self.run(move |conn| deposit.set_state(conn, state)).await
+    }
+
+    async fn get_zaps(&self) -> anyhow::Result<Vec<Zap>> {
+        self.run(Zap::get_zaps).await
+    }
+
+    async fn count_zaps(&self) -> anyhow::Result<i64> {
+        self.run(Zap::count).await
+    }
+
+    async fn get_zap(&self, invoice_id: i32) -> anyhow::Result<Option<Zap>> {
+        self.run(move |conn| Zap::get_by_id(conn, invoice_id)).await
+    }
+
+    async fn set_zap_event_id(&self, zap: &Zap, event_id: String) -> anyhow::Result<()> {
+        let zap = zap.clone();
+        self.run(move |conn| zap.set_event_id(conn, event_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::invoice::InvoiceState;
+    use prometheus::HistogramOpts;
+
+    /// Storage on a fresh in-memory SQLite database.
+    fn test_storage() -> DieselStorage {
+        // a single connection, every connection to `:memory:` is its own database
+        let pool = DbPool::sqlite(":memory:", 1).unwrap();
+        pool.run_migrations().unwrap();
+        let pool_wait = Histogram::with_opts(HistogramOpts::new("pool_wait", "test")).unwrap();
+        DieselStorage::new(pool, pool_wait)
+    }
+
+    fn new_user(name: &str, pubkey: u8, domain_id: Option<i32>) -> NewUser {
+        NewUser {
+            pubkey: format!("02{}", hex::encode([pubkey; 32])),
+            name: name.to_string(),
+            domain_id,
+        }
+    }
+
+    fn new_invoice(
+        user_id: i32,
+        amount_msats: i64,
+        state: InvoiceState,
+        pay_link_id: Option<i32>,
+    ) -> NewInvoice {
+        NewInvoice {
+            user_id,
+            bolt11: format!("lnbcrt{amount_msats}"),
+            amount_msats,
+            preimage: String::new(),
+            lnurlp_comment: None,
+            state: state as i32,
+            receive_id: None,
+            pay_link_id,
+            fiat_currency: None,
+            fiat_amount: None,
+            fiat_rate: None,
+            dedupe_key: None,
+            payment_hash: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn names_are_unique_per_domain() {
+        let storage = test_storage();
+        let other = storage
+            .insert_domain(NewDomain {
+                domain: "other.com".to_string(),
+                min_sendable: 1_000,
+                max_sendable: 1_000_000,
+                comment_allowed: 0,
+            })
+            .await
+            .unwrap();
+
+        let primary_alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        let other_alice = storage
+            .insert_user(new_user("alice", 1, Some(other.id)))
+            .await
+            .unwrap();
+        assert!(storage
+            .insert_user(new_user("alice", 2, None))
+            .await
+            .is_err());
+        assert!(storage
+            .insert_user(new_user("alice", 2, Some(other.id)))
+            .await
+            .is_err());
+
+        let found = storage.get_user_by_name(None, "alice").await.unwrap();
+        assert_eq!(found, Some(primary_alice));
+        let found = storage
+            .get_user_by_name(Some(other.id), "alice")
+            .await
+            .unwrap();
+        assert_eq!(found, Some(other_alice));
+        assert_eq!(storage.get_domain("other.com").await.unwrap(), Some(other));
+    }
+
+    #[tokio::test]
+    async fn pay_links() {
+        let storage = test_storage();
+        let alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        let bob = storage.insert_user(new_user("bob", 2, None)).await.unwrap();
+        let coffee = storage
+            .insert_pay_link(NewPayLink {
+                user_id: alice.id,
+                slug: "coffee".to_string(),
+                description: "A coffee".to_string(),
+                amount_msats: 5_000,
+                fiat_currency: None,
+                fiat_amount: None,
+            })
+            .await
+            .unwrap();
+
+        // slugs belong to their user
+        let found = storage
+            .get_pay_link_by_slug(alice.id, "coffee")
+            .await
+            .unwrap();
+        assert_eq!(found, Some(coffee.clone()));
+        let found = storage
+            .get_pay_link_by_slug(bob.id, "coffee")
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+
+        for state in [
+            InvoiceState::Settled,
+            InvoiceState::Settled,
+            InvoiceState::Pending,
+        ] {
+            storage
+                .insert_invoice(new_invoice(alice.id, 5_000, state, Some(coffee.id)), None)
+                .await
+                .unwrap();
+        }
+        // paid to the address, not through the link
+        storage
+            .insert_invoice(
+                new_invoice(alice.id, 7_000, InvoiceState::Settled, None),
+                None,
+            )
+            .await
+            .unwrap();
+        let totals = storage.get_pay_link_totals(&coffee).await.unwrap();
+        assert_eq!(totals, (2, 10_000));
+
+        // disabled links are kept for their totals
+        storage.set_pay_link_disabled(&coffee, true).await.unwrap();
+        let link = storage.get_pay_link(coffee.id).await.unwrap().unwrap();
+        assert!(link.disabled);
+        assert_eq!(storage.get_pay_links(alice.id).await.unwrap(), vec![link]);
+    }
+
+    #[tokio::test]
+    async fn names_ignore_case() {
+        let storage = test_storage();
+        let alice = storage
+            .insert_user(new_user("Alice", 1, None))
+            .await
+            .unwrap();
+
+        let found = storage.get_user_by_name(None, "ALICE").await.unwrap();
+        assert_eq!(found, Some(alice.clone()));
+        let found = storage.get_users_by_name("alice").await.unwrap();
+        assert_eq!(found, vec![alice]);
+
+        storage.ban_name("Bob").await.unwrap();
+        assert!(storage.is_name_banned("bob").await.unwrap());
+        assert!(storage.is_name_banned("BOB").await.unwrap());
+        storage.unban_name("bOb").await.unwrap();
+        assert!(!storage.is_name_banned("bob").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn users_with_deposit_address() {
+        let storage = test_storage();
+        let alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        storage.insert_user(new_user("bob", 2, None)).await.unwrap();
+
+        storage
+            .set_user_deposit_address(&alice, "bcrt1qalice")
+            .await
+            .unwrap();
+        let users = storage.get_users_with_deposit_address().await.unwrap();
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id, alice.id);
+        assert_eq!(users[0].deposit_address.as_deref(), Some("bcrt1qalice"));
+    }
+
+    #[tokio::test]
+    async fn search_users() {
+        let storage = test_storage();
+        let other = storage
+            .insert_domain(NewDomain {
+                domain: "other.com".to_string(),
+                min_sendable: 1_000,
+                max_sendable: 1_000_000,
+                comment_allowed: 0,
+            })
+            .await
+            .unwrap();
+        let alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        let bob = storage
+            .insert_user(new_user("bob_1", 2, Some(other.id)))
+            .await
+            .unwrap();
+        let carol = storage
+            .insert_user(new_user("bobx1", 3, None))
+            .await
+            .unwrap();
+
+        let search = |domain_id, query: Option<&str>| {
+            storage.search_users(UserFilter {
+                domain_id,
+                query: query.map(str::to_string),
+            })
+        };
+        assert_eq!(
+            search(None, None).await.unwrap(),
+            vec![alice.clone(), bob.clone(), carol.clone()]
+        );
+        assert_eq!(
+            search(Some(None), None).await.unwrap(),
+            vec![alice.clone(), carol.clone()]
+        );
+        assert_eq!(
+            search(Some(Some(other.id)), None).await.unwrap(),
+            vec![bob.clone()]
+        );
+        // `_` is matched literally
+        assert_eq!(search(None, Some("B_")).await.unwrap(), vec![bob.clone()]);
+        assert_eq!(
+            search(None, Some(alice.pubkey.as_str())).await.unwrap(),
+            vec![alice]
+        );
+        assert_eq!(storage.count_domain_users(&other).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn totals() {
+        let storage = test_storage();
+        let alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        let bob = storage.insert_user(new_user("bob", 2, None)).await.unwrap();
+        storage.set_user_disabled(&bob, true).await.unwrap();
+
+        for (amount_msats, state) in [
+            (1_000, InvoiceState::Settled),
+            (2_000, InvoiceState::Settled),
+            (4_000, InvoiceState::Pending),
+        ] {
+            storage
+                .insert_invoice(
+                    new_invoice(alice.id, amount_msats, state, None),
+                    Some("{}".to_string()),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(storage.count_users().await.unwrap(), (2, 1));
+        assert_eq!(storage.count_zaps().await.unwrap(), 3);
+        let mut totals = storage.get_invoice_totals().await.unwrap();
+        totals.sort();
+        assert_eq!(
+            totals,
+            vec![
+                (InvoiceState::Pending as i32, 1, 4_000),
+                (InvoiceState::Settled as i32, 2, 3_000),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_users_keep_their_history() {
+        let storage = test_storage();
+        let alice = storage
+            .insert_user(new_user("alice", 1, None))
+            .await
+            .unwrap();
+        let link = storage
+            .insert_pay_link(NewPayLink {
+                user_id: alice.id,
+                slug: "coffee".to_string(),
+                description: "A coffee".to_string(),
+                amount_msats: 5_000,
+                fiat_currency: None,
+                fiat_amount: None,
+            })
+            .await
+            .unwrap();
+        let invoice = storage
+            .insert_invoice(
+                new_invoice(alice.id, 5_000, InvoiceState::Settled, Some(link.id)),
+                Some("{}".to_string()),
+            )
+            .await
+            .unwrap();
+
+        storage.delete_user(&alice).await.unwrap();
+        assert_eq!(storage.get_user_by_name(None, "alice").await.unwrap(), None);
+        assert_eq!(
+            storage.get_invoice(invoice.id).await.unwrap(),
+            Some(invoice.clone())
+        );
+        assert!(storage.get_zap(invoice.id).await.unwrap().is_some());
+        assert_eq!(storage.get_pay_link(link.id).await.unwrap(), Some(link));
+
+        // the name can be registered again, without inheriting anything
+        let new_alice = storage
+            .insert_user(new_user("alice", 2, None))
+            .await
+            .unwrap();
+        assert_ne!(new_alice.id, alice.id);
+        assert!(storage
+            .get_pay_links(new_alice.id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+#![allow(dead_code)]
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use bitcoin::Network;
+use lnurl_spark::backend::mock::MockBackend;
+use lnurl_spark::config::{PublicUrl, Settings};
+use lnurl_spark::invoice_events;
+use lnurl_spark::metrics::Metrics;
+use lnurl_spark::price::{Currency, FixedRateSource};
+use lnurl_spark::rate_limit::RateLimits;
+use lnurl_spark::signer::ZapSigner;
+use lnurl_spark::storage::{DbPool, DieselStorage};
+use lnurl_spark::{router, State};
+use nostr::Keys;
+use serde_json::Value;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tower::ServiceExt;
+
+pub const DOMAIN: &str = "example.com";
+pub const MIN_SENDABLE: u64 = 1_000;
+pub const MAX_SENDABLE: u64 = 1_000_000;
+pub const COMMENT_ALLOWED: u32 = 10;
+pub const BTC_USD: &str = "USD:100000";
+
+pub fn settings() -> Settings {
+    Settings {
+        min_sendable: MIN_SENDABLE,
+        max_sendable: MAX_SENDABLE,
+        comment_allowed: COMMENT_ALLOWED,
+        onchain_min_sendable: None,
+        onchain_max_sendable: None,
+        invoice_reuse_secs: 60,
+        reserved_names: vec!["admin".to_string()],
+        rate_limit_ip: 0,
+        rate_limit_name: 0,
+        rate_limit_register: 0,
+    }
+}
+
+/// State backed by an in-memory SQLite database and the mock backend.
+pub fn test_state(settings: Settings) -> State {
+    // a single connection, every connection to `:memory:` is its own database
+    let db_pool = DbPool::sqlite(":memory:", 1).unwrap();
+    db_pool.run_migrations().unwrap();
+    let metrics = Metrics::new().unwrap();
+    let storage = DieselStorage::new(db_pool, metrics.db_pool_wait_seconds.clone());
+
+    let keys = Keys::generate();
+    let (zap_receipts, _) = mpsc::unbounded_channel();
+    let (invoice_events, _) = broadcast::channel(invoice_events::CHANNEL_CAPACITY);
+
+    State {
+        storage: Arc::new(storage),
+        nostr_pubkey: Arc::new(OnceLock::from(keys.public_key())),
+        signer: ZapSigner::Keys(keys),
+        zap_receipts,
+        invoice_events,
+        user_streams: Default::default(),
+        invoice_streams: Default::default(),
+        wallet: Arc::new(MockBackend::new(
+            Network::Regtest,
+            Duration::from_secs(3600),
+        )),
+        metrics: Arc::new(metrics),
+        wallet_status: Default::default(),
+        rate_limits: Arc::new(RateLimits::new(0, 0, 0)),
+        prices: Arc::new(FixedRateSource::parse(&[BTC_USD.to_string()]).unwrap()),
+        domain: DOMAIN.to_string(),
+        public_url: PublicUrl::parse(&format!("https://{DOMAIN}"), Network::Regtest).unwrap(),
+        settings: Arc::new(RwLock::new(settings)),
+        currencies: vec![Currency::from_code("USD").unwrap()],
+        admin_token: None,
+        admin_pubkeys: vec![],
+        trust_proxy: false,
+    }
+}
+
+pub struct TestApp {
+    pub state: State,
+    pub router: Router,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        Self::with_settings(settings())
+    }
+
+    pub fn with_settings(settings: Settings) -> Self {
+        Self::with_state(test_state(settings))
+    }
+
+    pub fn with_state(state: State) -> Self {
+        let router = router(state.clone());
+        Self { state, router }
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        let req = Request::get(uri)
+            .header(header::HOST, DOMAIN)
+            .body(Body::empty())
+            .unwrap();
+        self.send(req).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        let req = Request::post(uri)
+            .header(header::HOST, DOMAIN)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        self.send(req).await
+    }
+
+    /// Sends `req` as is, leaving the body unread, e.g. for event streams.
+    pub async fn request(&self, req: Request<Body>) -> Response {
+        self.router.clone().oneshot(req).await.unwrap()
+    }
+
+    async fn send(&self, req: Request<Body>) -> (StatusCode, Value) {
+        let resp = self.request(req).await;
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    /// Registers `name` with a fresh pubkey.
+    pub async fn register(&self, name: &str) -> (StatusCode, Value) {
+        let pubkey = Keys::generate().public_key().to_hex();
+        self.post(
+            "/v1/register",
+            serde_json::json!({ "name": name, "pubkey": format!("02{pubkey}") }),
+        )
+        .await
+    }
+}
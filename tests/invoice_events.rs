@@ -0,0 +1,256 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use base64::Engine;
+use bitcoin::hashes::{sha256, Hash};
+use common::*;
+use lightning_invoice::Bolt11Invoice;
+use lnurl_spark::invoice_events::{backfill_payment_hashes, MAX_INVOICE_STREAMS, MAX_USER_STREAMS};
+use lnurl_spark::models::domain::NewDomain;
+use lnurl_spark::models::invoice::{InvoiceState, NewInvoice};
+use nostr::{EventBuilder, JsonUtil, Keys, Kind, Tag};
+
+const USER_STREAM: &str = "/v1/users/alice/invoices/events";
+
+/// A NIP-98 `Authorization` header value for a GET of `url`.
+fn nip98_auth(keys: &Keys, url: &str) -> String {
+    let event = EventBuilder::new(Kind::HttpAuth, "")
+        .tag(Tag::parse(["u", url]).unwrap())
+        .tag(Tag::parse(["method", "GET"]).unwrap())
+        .sign_with_keys(keys)
+        .unwrap();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(event.as_json());
+    format!("Nostr {encoded}")
+}
+
+async fn register(app: &TestApp, keys: &Keys, domain: Option<&str>) {
+    let pubkey = format!("02{}", keys.public_key().to_hex());
+    let (status, body) = app
+        .post(
+            "/v1/register",
+            serde_json::json!({ "name": "alice", "pubkey": pubkey, "domain": domain }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+}
+
+/// Opens the user stream on `host`, signing the auth event for `url`.
+async fn open_stream(app: &TestApp, keys: &Keys, host: &str, url: &str) -> Response {
+    open_stream_at(app, keys, host, USER_STREAM, url).await
+}
+
+/// Like [`open_stream`] with the request sent to `target`.
+async fn open_stream_at(
+    app: &TestApp,
+    keys: &Keys,
+    host: &str,
+    target: &str,
+    url: &str,
+) -> Response {
+    let req = Request::get(target)
+        .header(header::HOST, host)
+        .header(header::AUTHORIZATION, nip98_auth(keys, url))
+        .body(Body::empty())
+        .unwrap();
+    app.request(req).await
+}
+
+#[tokio::test]
+async fn user_stream_auth_uses_request_host() {
+    let app = TestApp::new();
+    app.state
+        .storage
+        .insert_domain(NewDomain {
+            domain: "other.com".to_string(),
+            min_sendable: MIN_SENDABLE as i64,
+            max_sendable: MAX_SENDABLE as i64,
+            comment_allowed: COMMENT_ALLOWED as i32,
+        })
+        .await
+        .unwrap();
+    let keys = Keys::generate();
+    register(&app, &keys, Some("other.com")).await;
+
+    let url = format!("https://other.com{USER_STREAM}");
+    let resp = open_stream(&app, &keys, "other.com", &url).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // signed for the primary domain but sent to other.com
+    let url = format!("https://{DOMAIN}{USER_STREAM}");
+    let resp = open_stream(&app, &keys, "other.com", &url).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn user_stream_auth_uses_public_url() {
+    let app = TestApp::new();
+    let keys = Keys::generate();
+    register(&app, &keys, None).await;
+
+    // proxies may send absolute-form targets, the url is still the public one
+    let url = format!("https://{DOMAIN}{USER_STREAM}");
+    let target = format!("http://127.0.0.1:8080{USER_STREAM}");
+    let resp = open_stream_at(&app, &keys, DOMAIN, &target, &url).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // the query is part of the signed url
+    let target = format!("{USER_STREAM}?since=1");
+    let resp = open_stream_at(&app, &keys, DOMAIN, &target, &url).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let url = format!("https://{DOMAIN}{target}");
+    let resp = open_stream_at(&app, &keys, DOMAIN, &target, &url).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn user_stream_of_disabled_user() {
+    let app = TestApp::new();
+    let keys = Keys::generate();
+    register(&app, &keys, None).await;
+    let user = app
+        .state
+        .storage
+        .get_user_by_name(None, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    app.state
+        .storage
+        .set_user_disabled(&user, true)
+        .await
+        .unwrap();
+
+    let url = format!("https://{DOMAIN}{USER_STREAM}");
+    let resp = open_stream(&app, &keys, DOMAIN, &url).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn invoice_stream_limit() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let (status, body) = app.get("/get-invoice/alice?amount=5000").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let bolt11: Bolt11Invoice = body["pr"].as_str().unwrap().parse().unwrap();
+    let events = format!("/v1/invoices/{}/events", bolt11.payment_hash());
+
+    let open = || {
+        app.request(
+            Request::get(&events)
+                .header(header::HOST, DOMAIN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let mut streams = Vec::new();
+    for _ in 0..MAX_INVOICE_STREAMS {
+        let resp = open().await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        streams.push(resp);
+    }
+
+    let resp = open().await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // closing a stream frees its slot
+    streams.pop();
+    let resp = open().await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn user_stream_limit() {
+    let app = TestApp::new();
+    let keys = Keys::generate();
+    register(&app, &keys, None).await;
+    let url = format!("https://{DOMAIN}{USER_STREAM}");
+
+    let mut streams = Vec::new();
+    for _ in 0..MAX_USER_STREAMS {
+        let resp = open_stream(&app, &keys, DOMAIN, &url).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        streams.push(resp);
+    }
+
+    let resp = open_stream(&app, &keys, DOMAIN, &url).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // closing a stream frees its slot
+    streams.pop();
+    let resp = open_stream(&app, &keys, DOMAIN, &url).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn payment_hash_backfill() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let user = app
+        .state
+        .storage
+        .get_user_by_name(None, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // an invoice stored before invoices had a payment hash
+    let created = app
+        .state
+        .wallet
+        .create_invoice(5_000, sha256::Hash::hash(b"metadata"), user.pubkey())
+        .await
+        .unwrap();
+    let payment_hash = created.bolt11.payment_hash().to_string();
+    app.state
+        .storage
+        .insert_invoice(
+            NewInvoice {
+                user_id: user.id,
+                bolt11: created.bolt11.to_string(),
+                amount_msats: 5_000,
+                preimage: created.preimage.unwrap_or_default(),
+                lnurlp_comment: None,
+                state: InvoiceState::Pending as i32,
+                receive_id: Some(created.id),
+                pay_link_id: None,
+                fiat_currency: None,
+                fiat_amount: None,
+                fiat_rate: None,
+                dedupe_key: None,
+                payment_hash: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let (status, _) = app
+        .get(&format!("/v1/invoices/{payment_hash}/events"))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    assert_eq!(
+        backfill_payment_hashes(app.state.storage.as_ref())
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        backfill_payment_hashes(app.state.storage.as_ref())
+            .await
+            .unwrap(),
+        0
+    );
+
+    let resp = app
+        .request(
+            Request::get(format!("/v1/invoices/{payment_hash}/events"))
+                .header(header::HOST, DOMAIN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
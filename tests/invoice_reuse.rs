@@ -0,0 +1,134 @@
+mod common;
+
+use axum::http::StatusCode;
+use bitcoin::Network;
+use common::*;
+use lnurl_spark::backend::mock::MockBackend;
+use lnurl_spark::config::Settings;
+use lnurl_spark::models::invoice::{Invoice, InvoiceState, NewInvoice};
+use lnurl_spark::storage::is_unique_violation;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn callback(app: &TestApp, query: &str) -> String {
+    let (status, body) = app.get(&format!("/get-invoice/alice?{query}")).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    body["pr"].as_str().unwrap().to_string()
+}
+
+async fn stored(app: &TestApp, bolt11: &str) -> Invoice {
+    app.state
+        .storage
+        .get_invoices()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|i| i.bolt11 == bolt11)
+        .expect("invoice is stored")
+}
+
+#[tokio::test]
+async fn same_idempotency_key_reuses_invoice() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let first = callback(&app, "amount=5000&idempotencyKey=a").await;
+    let second = callback(&app, "amount=5000&idempotencyKey=a").await;
+    assert_eq!(first, second);
+    assert_eq!(app.state.metrics.invoices_reused.get(), 1);
+    assert_eq!(app.state.metrics.invoices_created.get(), 1);
+
+    // the key only matches together with the rest of the callback
+    let other_amount = callback(&app, "amount=6000&idempotencyKey=a").await;
+    assert_ne!(other_amount, first);
+    let other_key = callback(&app, "amount=5000&idempotencyKey=b").await;
+    assert_ne!(other_key, first);
+}
+
+#[tokio::test]
+async fn callbacks_without_key_get_new_invoices() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    // e.g. two payers sending the same amount
+    let first = callback(&app, "amount=5000").await;
+    let second = callback(&app, "amount=5000").await;
+    assert_ne!(first, second);
+    assert_eq!(app.state.metrics.invoices_reused.get(), 0);
+    assert_eq!(app.state.metrics.invoices_created.get(), 2);
+    assert_eq!(stored(&app, &first).await.dedupe_key, None);
+}
+
+#[tokio::test]
+async fn paid_invoice_is_not_reused() {
+    let mut state = test_state(settings());
+    // payments settle right away, before the invoice subscriber polls them
+    state.wallet = Arc::new(MockBackend::new(Network::Regtest, Duration::ZERO));
+    let app = TestApp::with_state(state);
+    app.register("alice").await;
+
+    let first = callback(&app, "amount=5000&idempotencyKey=a").await;
+    let second = callback(&app, "amount=5000&idempotencyKey=a").await;
+    assert_ne!(first, second);
+
+    // the key moved to the new invoice
+    let first = stored(&app, &first).await;
+    let second = stored(&app, &second).await;
+    assert_eq!(first.state, InvoiceState::Pending as i32);
+    assert_eq!(first.dedupe_key, None);
+    assert!(second.dedupe_key.is_some());
+}
+
+#[tokio::test]
+async fn reuse_disabled() {
+    let app = TestApp::with_settings(Settings {
+        invoice_reuse_secs: 0,
+        ..settings()
+    });
+    app.register("alice").await;
+
+    let first = callback(&app, "amount=5000&idempotencyKey=a").await;
+    let second = callback(&app, "amount=5000&idempotencyKey=a").await;
+    assert_ne!(first, second);
+    assert_eq!(stored(&app, &first).await.dedupe_key, None);
+}
+
+#[tokio::test]
+async fn one_pending_invoice_per_key() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let bolt11 = callback(&app, "amount=5000&idempotencyKey=a").await;
+    let existing = stored(&app, &bolt11).await;
+
+    let duplicate = |state: InvoiceState| NewInvoice {
+        user_id: existing.user_id,
+        bolt11: existing.bolt11.clone(),
+        amount_msats: existing.amount_msats,
+        preimage: String::new(),
+        lnurlp_comment: None,
+        state: state as i32,
+        receive_id: None,
+        pay_link_id: None,
+        fiat_currency: None,
+        fiat_amount: None,
+        fiat_rate: None,
+        dedupe_key: existing.dedupe_key.clone(),
+        payment_hash: None,
+    };
+
+    // what a concurrent callback with the same key runs into
+    let err = app
+        .state
+        .storage
+        .insert_invoice(duplicate(InvoiceState::Pending), None)
+        .await
+        .unwrap_err();
+    assert!(is_unique_violation(&err), "{err:?}");
+
+    // settled and cancelled invoices keep their key
+    app.state
+        .storage
+        .insert_invoice(duplicate(InvoiceState::Settled), None)
+        .await
+        .unwrap();
+}
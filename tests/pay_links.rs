@@ -0,0 +1,161 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use common::*;
+use lightning_invoice::Bolt11Invoice;
+use lnurl_spark::models::invoice::Invoice;
+use lnurl_spark::models::pay_link::NewPayLink;
+use std::str::FromStr;
+
+async fn add_pay_link(app: &TestApp, slug: &str, amount_msats: i64, fiat: Option<(&str, i64)>) {
+    let user = app
+        .state
+        .storage
+        .get_user_by_name(None, "alice")
+        .await
+        .unwrap()
+        .unwrap();
+    app.state
+        .storage
+        .insert_pay_link(NewPayLink {
+            user_id: user.id,
+            slug: slug.to_string(),
+            description: format!("A {slug}"),
+            amount_msats,
+            fiat_currency: fiat.map(|(c, _)| c.to_string()),
+            fiat_amount: fiat.map(|(_, a)| a),
+        })
+        .await
+        .unwrap();
+}
+
+async fn stored_invoice(app: &TestApp, body: &serde_json::Value) -> Invoice {
+    let pr = Bolt11Invoice::from_str(body["pr"].as_str().unwrap()).unwrap();
+    app.state
+        .storage
+        .get_invoice_by_payment_hash(&pr.payment_hash().to_string())
+        .await
+        .unwrap()
+        .expect("invoice is stored")
+}
+
+#[tokio::test]
+async fn sats_pay_link() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    add_pay_link(&app, "tip", 100_000, None).await;
+
+    let (status, body) = app.get("/.well-known/lnurlp/alice/tip").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["callback"],
+        "https://example.com/get-invoice/alice/tip"
+    );
+    assert_eq!(body["minSendable"], 100_000);
+    assert_eq!(body["maxSendable"], 100_000);
+
+    let (status, body) = app.get("/get-invoice/alice/tip?amount=100000").await;
+    assert_eq!(status, StatusCode::OK);
+    let invoice = stored_invoice(&app, &body).await;
+    assert_eq!(invoice.amount_msats, 100_000);
+    assert!(invoice.pay_link_id.is_some());
+    assert_eq!(invoice.fiat_currency, None);
+
+    // sats priced links must be paid exactly
+    let (status, body) = app.get("/get-invoice/alice/tip?amount=101000").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "amount_out_of_bounds");
+}
+
+#[tokio::test]
+async fn fiat_pay_link() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    // $0.50, 500 sats at $100,000 per bitcoin
+    add_pay_link(&app, "coffee", 1, Some(("USD", 50))).await;
+
+    let (status, body) = app.get("/.well-known/lnurlp/alice/coffee").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["minSendable"], 500_000);
+    assert_eq!(body["maxSendable"], 500_000);
+
+    let (status, body) = app.get("/get-invoice/alice/coffee?amount=500000").await;
+    assert_eq!(status, StatusCode::OK);
+    let invoice = stored_invoice(&app, &body).await;
+    assert_eq!(invoice.amount_msats, 500_000);
+    assert_eq!(invoice.fiat_currency.as_deref(), Some("USD"));
+    assert_eq!(invoice.fiat_amount, Some(50));
+    assert_eq!(invoice.fiat_rate, Some(100_000.0));
+
+    // paying in the link's currency
+    let (status, body) = app.get("/get-invoice/alice/coffee?amount=50.USD").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(stored_invoice(&app, &body).await.amount_msats, 500_000);
+}
+
+#[tokio::test]
+async fn fiat_pay_link_tolerance() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    add_pay_link(&app, "coffee", 1, Some(("USD", 50))).await;
+
+    // the price may have moved by up to 1% since the wallet fetched the link
+    for amount in [495_000, 505_000] {
+        let (status, _) = app
+            .get(&format!("/get-invoice/alice/coffee?amount={amount}"))
+            .await;
+        assert_eq!(status, StatusCode::OK, "{amount}");
+    }
+
+    for amount in [494_000, 506_000] {
+        let (status, body) = app
+            .get(&format!("/get-invoice/alice/coffee?amount={amount}"))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{amount}");
+        assert_eq!(body["code"], "amount_out_of_bounds");
+    }
+
+    let (status, body) = app.get("/get-invoice/alice/coffee?amount=50.EUR").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "unsupported_currency");
+}
+
+#[tokio::test]
+async fn unknown_pay_link() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let (status, body) = app.get("/get-invoice/alice/nope?amount=1000").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "pay_link_not_found");
+
+    let (status, body) = app.get("/.well-known/lnurlp/alice/nope").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "pay_link_not_found");
+}
+
+#[tokio::test]
+async fn pay_links_are_whole_sats() {
+    let mut state = test_state(settings());
+    state.admin_token = Some("secret".to_string());
+    let app = TestApp::with_state(state);
+    app.register("alice").await;
+
+    for (amount_msats, expected) in [(1_500, StatusCode::BAD_REQUEST), (2_000, StatusCode::OK)] {
+        let req = Request::post("/admin/users/alice/links")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "slug": format!("tip-{amount_msats}"),
+                    "description": "A tip",
+                    "amount_msats": amount_msats,
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let resp = app.request(req).await;
+        assert_eq!(resp.status(), expected, "{amount_msats}");
+    }
+}
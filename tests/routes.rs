@@ -0,0 +1,294 @@
+mod common;
+
+use axum::http::StatusCode;
+use bitcoin::hashes::{sha256, Hash};
+use common::*;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+use lnurl_spark::config::Settings;
+use std::str::FromStr;
+
+fn invoice(body: &serde_json::Value) -> Bolt11Invoice {
+    Bolt11Invoice::from_str(body["pr"].as_str().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn register_name() {
+    let app = TestApp::new();
+
+    let (status, body) = app.register("alice").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "alice");
+    assert_eq!(body["domain"], DOMAIN);
+
+    let (status, body) = app.register("alice").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["status"], "ERROR");
+    assert_eq!(body["code"], "name_taken");
+}
+
+#[tokio::test]
+async fn names_ignore_case() {
+    let app = TestApp::new();
+
+    let (status, body) = app.register("Alice").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "alice");
+
+    let (status, body) = app.register("ALICE").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "name_taken");
+
+    let (status, _) = app.get("/.well-known/lnurlp/aLiCe").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn register_pubkey_twice() {
+    let app = TestApp::new();
+    let pubkey = format!("02{}", nostr::Keys::generate().public_key().to_hex());
+
+    let (status, _) = app
+        .post(
+            "/v1/register",
+            serde_json::json!({ "name": "alice", "pubkey": pubkey }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // the unique index rejects the insert, which must not look like an outage
+    let (status, body) = app
+        .post(
+            "/v1/register",
+            serde_json::json!({ "name": "bob", "pubkey": pubkey }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "pubkey_taken");
+}
+
+#[tokio::test]
+async fn register_reserved_name() {
+    let app = TestApp::new();
+
+    let (status, body) = app.register("Admin").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "name_banned");
+}
+
+#[tokio::test]
+async fn register_unknown_domain() {
+    let app = TestApp::new();
+    let pubkey = nostr::Keys::generate().public_key().to_hex();
+
+    let (status, body) = app
+        .post(
+            "/v1/register",
+            serde_json::json!({
+                "name": "alice",
+                "pubkey": format!("02{pubkey}"),
+                "domain": "other.com",
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "domain_not_found");
+}
+
+#[tokio::test]
+async fn lnurl_pay_response() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let (status, body) = app.get("/.well-known/lnurlp/alice").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["tag"], "payRequest");
+    assert_eq!(body["callback"], "https://example.com/get-invoice/alice");
+    assert_eq!(body["minSendable"], MIN_SENDABLE);
+    assert_eq!(body["maxSendable"], MAX_SENDABLE);
+    assert_eq!(body["commentAllowed"], COMMENT_ALLOWED);
+    assert_eq!(body["allowsNostr"], true);
+    assert_eq!(
+        body["nostrPubkey"],
+        app.state.nostr_pubkey.get().unwrap().to_hex().as_str()
+    );
+    assert_eq!(body["currencies"][0]["code"], "USD");
+
+    let metadata: serde_json::Value =
+        serde_json::from_str(body["metadata"].as_str().unwrap()).unwrap();
+    assert!(metadata
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|entry| entry[0] == "text/identifier" && entry[1] == "alice@example.com"));
+}
+
+#[tokio::test]
+async fn get_invoice() {
+    let app = TestApp::new();
+    app.register("alice").await;
+    let (_, pay) = app.get("/.well-known/lnurlp/alice").await;
+
+    let (status, body) = app.get("/get-invoice/alice?amount=5000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "OK");
+
+    let invoice = invoice(&body);
+    assert_eq!(invoice.amount_milli_satoshis(), Some(5_000));
+    let metadata = pay["metadata"].as_str().unwrap();
+    match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Hash(hash) => {
+            assert_eq!(hash.0, sha256::Hash::hash(metadata.as_bytes()))
+        }
+        Bolt11InvoiceDescriptionRef::Direct(_) => panic!("expected a description hash"),
+    }
+
+    let payment_hash = invoice.payment_hash().to_string();
+    let stored = app
+        .state
+        .storage
+        .get_invoice_by_payment_hash(&payment_hash)
+        .await
+        .unwrap()
+        .expect("invoice is stored");
+    assert_eq!(stored.amount_msats, 5_000);
+    assert_eq!(stored.bolt11, invoice.to_string());
+}
+
+#[tokio::test]
+async fn get_invoice_fiat_amount() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    // $0.50 at $100,000 per bitcoin
+    let (status, body) = app.get("/get-invoice/alice?amount=50.USD").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(invoice(&body).amount_milli_satoshis(), Some(500_000));
+}
+
+#[tokio::test]
+async fn get_invoice_errors() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    let cases = [
+        (
+            "/get-invoice/alice",
+            StatusCode::BAD_REQUEST,
+            "missing_amount",
+        ),
+        (
+            "/get-invoice/alice?amount=999",
+            StatusCode::BAD_REQUEST,
+            "amount_out_of_bounds",
+        ),
+        (
+            "/get-invoice/alice?amount=1000001",
+            StatusCode::BAD_REQUEST,
+            "amount_out_of_bounds",
+        ),
+        (
+            "/get-invoice/alice?amount=1500",
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+        ),
+        (
+            "/get-invoice/alice?amount=1000&comment=more%20than%20ten",
+            StatusCode::BAD_REQUEST,
+            "comment_too_long",
+        ),
+        (
+            "/get-invoice/alice?amount=1000&nostr=not-an-event",
+            StatusCode::BAD_REQUEST,
+            "invalid_zap_request",
+        ),
+        (
+            "/get-invoice/alice?amount=500.XYZ",
+            StatusCode::BAD_REQUEST,
+            "unsupported_currency",
+        ),
+        (
+            "/get-invoice/bob?amount=1000",
+            StatusCode::NOT_FOUND,
+            "user_not_found",
+        ),
+    ];
+
+    for (uri, expected_status, expected_code) in cases {
+        let (status, body) = app.get(uri).await;
+        assert_eq!(status, expected_status, "{uri}");
+        assert_eq!(body["code"], expected_code, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn unknown_route() {
+    let app = TestApp::new();
+
+    let (status, body) = app.get("/nope").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "route_not_found");
+}
+
+#[tokio::test]
+async fn invoice_failures_are_labelled_by_error_code() {
+    let app = TestApp::new();
+    app.register("alice").await;
+
+    app.get("/get-invoice/alice").await;
+    app.get("/get-invoice/alice?amount=1").await;
+    app.get("/get-invoice/alice?amount=2").await;
+    app.get("/get-invoice/bob?amount=1000").await;
+
+    let failures = &app.state.metrics.invoice_failures;
+    assert_eq!(failures.with_label_values(&["missing_amount"]).get(), 1);
+    assert_eq!(
+        failures.with_label_values(&["amount_out_of_bounds"]).get(),
+        2
+    );
+    assert_eq!(failures.with_label_values(&["user_not_found"]).get(), 1);
+    assert_eq!(failures.with_label_values(&["internal_error"]).get(), 0);
+}
+
+#[tokio::test]
+async fn onchain_fallback() {
+    let app = TestApp::with_settings(Settings {
+        onchain_min_sendable: Some(500_000),
+        onchain_max_sendable: Some(10_000_000),
+        ..settings()
+    });
+    app.register("alice").await;
+
+    let (_, body) = app.get("/.well-known/lnurlp/alice").await;
+    assert_eq!(body["maxSendable"], 10_000_000);
+
+    // below onchain_min_sendable, lightning only
+    let (status, body) = app.get("/get-invoice/alice?amount=400000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["pr"].is_string());
+    assert!(body.get("bip21").is_none());
+
+    // both options
+    let (status, body) = app.get("/get-invoice/alice?amount=600000").await;
+    assert_eq!(status, StatusCode::OK);
+    let invoice = invoice(&body);
+    let bip21 = body["bip21"].as_str().unwrap();
+    assert!(bip21.starts_with("bitcoin:bcrt1"), "{bip21}");
+    assert!(bip21.ends_with(&format!("?amount=0.000006&lightning={invoice}")));
+
+    // above max_sendable, on-chain only
+    let (status, body) = app.get("/get-invoice/alice?amount=5000000").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("pr").is_none());
+    let bip21 = body["bip21"].as_str().unwrap();
+    assert!(bip21.ends_with("?amount=0.00005"), "{bip21}");
+    assert_eq!(app.state.storage.get_invoices().await.unwrap().len(), 2);
+
+    // zaps need an invoice
+    let (status, body) = app.get("/get-invoice/alice?amount=5000000&nostr=x").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "amount_out_of_bounds");
+
+    let (status, body) = app.get("/get-invoice/alice?amount=10000001").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "amount_out_of_bounds");
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::*;
+use lightning_invoice::Bolt11Invoice;
+use lnurl_spark::invoice_subscriber::{start_zap_receipt_publisher, ZapJob};
+use lnurl_spark::signer::{start_public_key_fetch, ZapSigner};
+use lnurl_spark::State;
+use nostr::{EventBuilder, JsonUtil, Keys, Kind, PublicKey, Tag};
+use nostr_connect::prelude::{
+    NostrConnectKeys, NostrConnectRemoteSigner, NostrConnectRequest, NostrConnectSignerActions,
+};
+use nostr_relay_builder::MockRelay;
+use nostr_sdk::{Client, Filter};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+/// How long the remote signer gets to answer before a request fails.
+const SIGNER_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct ApproveAll;
+
+impl NostrConnectSignerActions for ApproveAll {
+    fn approve(&self, _public_key: &PublicKey, _req: &NostrConnectRequest) -> bool {
+        true
+    }
+}
+
+/// Polls `check` until it returns something, failing the test after `timeout`.
+async fn wait_for<T, F, Fut>(timeout: Duration, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Option<T>>,
+{
+    let start = Instant::now();
+    loop {
+        if let Some(value) = check().await {
+            return value;
+        }
+        assert!(start.elapsed() < timeout, "timed out");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+async fn published_receipt(state: &State, invoice_id: i32) -> Option<String> {
+    state
+        .storage
+        .get_zap(invoice_id)
+        .await
+        .unwrap()
+        .and_then(|z| z.event_id)
+}
+
+#[tokio::test]
+async fn remote_signer_signs_queued_receipts() {
+    let relay = MockRelay::run().await.unwrap();
+    let relay_url = relay.url().to_string();
+
+    // the bunker isn't serving yet, like a remote signer that is offline
+    let user = Keys::generate();
+    let keys = NostrConnectKeys {
+        signer: Keys::generate(),
+        user: user.clone(),
+    };
+    let bunker =
+        NostrConnectRemoteSigner::new(keys, [relay_url.as_str()], Some("secret"), None).unwrap();
+
+    let mut state = test_state(settings());
+    state.signer = ZapSigner::remote(&bunker.bunker_uri().to_string(), SIGNER_TIMEOUT).unwrap();
+    state.nostr_pubkey = Arc::new(OnceLock::new());
+    let (zap_receipts, jobs) = mpsc::unbounded_channel();
+    state.zap_receipts = zap_receipts;
+    tokio::spawn(start_zap_receipt_publisher(state.clone(), jobs));
+    let app = TestApp::with_state(state.clone());
+    app.register("alice").await;
+
+    // zaps aren't advertised until the signer's pubkey is known
+    let (_, pay) = app.get("/.well-known/lnurlp/alice").await;
+    assert!(pay.get("allowsNostr").is_none());
+    assert!(pay.get("nostrPubkey").is_none());
+
+    let sender = Keys::generate();
+    let zap_request = EventBuilder::new(Kind::ZapRequest, "")
+        .tag(Tag::parse(["relays", relay_url.as_str()]).unwrap())
+        .tag(Tag::parse(["amount", "5000"]).unwrap())
+        .tag(Tag::public_key(user.public_key()))
+        .sign_with_keys(&sender)
+        .unwrap();
+    let (status, body) = app
+        .get(&format!(
+            "/get-invoice/alice?amount=5000&nostr={}",
+            urlencoding::encode(&zap_request.as_json())
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let pr = Bolt11Invoice::from_str(body["pr"].as_str().unwrap()).unwrap();
+    let invoice = state
+        .storage
+        .get_invoice_by_payment_hash(&pr.payment_hash().to_string())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // the first attempt times out waiting for the signer
+    state
+        .zap_receipts
+        .send(ZapJob::new(invoice.id, None))
+        .unwrap();
+    tokio::time::sleep(SIGNER_TIMEOUT * 3).await;
+    assert_eq!(published_receipt(&state, invoice.id).await, None);
+
+    tokio::spawn(async move { bunker.serve(ApproveAll).await });
+
+    tokio::spawn(start_public_key_fetch(
+        state.signer.clone(),
+        state.nostr_pubkey.clone(),
+    ));
+    let pubkey = wait_for(Duration::from_secs(10), || {
+        let pubkey = state.nostr_pubkey.clone();
+        async move { pubkey.get().copied() }
+    })
+    .await;
+    assert_eq!(pubkey, user.public_key());
+    let (_, pay) = app.get("/.well-known/lnurlp/alice").await;
+    assert_eq!(pay["allowsNostr"], true);
+    assert_eq!(pay["nostrPubkey"], user.public_key().to_hex().as_str());
+
+    // the retry is signed once the signer is back
+    let event_id = wait_for(Duration::from_secs(30), || {
+        published_receipt(&state, invoice.id)
+    })
+    .await;
+
+    let client = Client::default();
+    client.add_relay(relay_url.as_str()).await.unwrap();
+    client.connect().await;
+    let receipts = client
+        .fetch_events(Filter::new().kind(Kind::ZapReceipt), Duration::from_secs(5))
+        .await
+        .unwrap();
+    let receipt = receipts
+        .into_iter()
+        .find(|e| e.id.to_hex() == event_id)
+        .expect("receipt is on the relay");
+    assert_eq!(receipt.pubkey, user.public_key());
+    receipt.verify().unwrap();
+}